@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{TerrainMap, TilePos, TileType};
+
+/// Neighbour bits, clockwise starting from north.
+const NORTH: u8 = 1;
+const NORTH_EAST: u8 = 1 << 1;
+const EAST: u8 = 1 << 2;
+const SOUTH_EAST: u8 = 1 << 3;
+const SOUTH: u8 = 1 << 4;
+const SOUTH_WEST: u8 = 1 << 5;
+const WEST: u8 = 1 << 6;
+const NORTH_WEST: u8 = 1 << 7;
+
+const OFFSETS: [(i32, i32); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/// A tile drawn from a transition tileset instead of a single texture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Autotile {
+    pub tileset: String,
+    pub layout: TilesetLayout,
+    /// Other tile types this one blends into as if they were the same, e.g. grass into forest.
+    #[serde(default)]
+    pub connects: Vec<TileType>,
+}
+
+impl Autotile {
+    pub fn connects_to(&self, own: TileType, other: TileType) -> bool {
+        own == other || self.connects.contains(&other)
+    }
+}
+
+/// How the variants are laid out in a tileset image, left to right and top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TilesetLayout {
+    /// 4 by 4 variants, indexed by which of the north, east, south and west neighbours connect
+    /// (bits `1`, `2`, `4` and `8` in that order).
+    MarchingSquares,
+    /// 47 variants in rows of 8, sorted by their neighbour mask. Corners only count when both
+    /// sides next to them connect as well.
+    Blob,
+}
+
+impl TilesetLayout {
+    /// Columns and rows of the tileset grid.
+    pub fn grid(self) -> (u32, u32) {
+        match self {
+            TilesetLayout::MarchingSquares => (4, 4),
+            TilesetLayout::Blob => (8, 6),
+        }
+    }
+
+    /// Which variant to use for a tile with the given neighbour mask.
+    pub fn variant(self, neighbours: u8) -> u32 {
+        match self {
+            TilesetLayout::MarchingSquares => [NORTH, EAST, SOUTH, WEST]
+                .iter()
+                .enumerate()
+                .filter(|(_, bit)| neighbours & **bit != 0)
+                .map(|(i, _)| 1 << i)
+                .sum(),
+            TilesetLayout::Blob => BLOB_VARIANTS[reduce(neighbours) as usize] as u32,
+        }
+    }
+}
+
+/// Mask of the neighbours of `pos` that `connects` accepts. Unloaded neighbours count as
+/// connected, the edge gets fixed up once their chunk arrives.
+pub fn neighbours(map: &TerrainMap, pos: TilePos, connects: impl Fn(TileType) -> bool) -> u8 {
+    OFFSETS
+        .iter()
+        .enumerate()
+        .filter(|(_, (x, y))| map.tile_type(pos.offset(*x, *y)).is_none_or(&connects))
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+/// Drops corners whose neighbouring sides don't both connect.
+const fn reduce(mask: u8) -> u8 {
+    let mut mask = mask;
+    let corners = [
+        (NORTH_EAST, NORTH | EAST),
+        (SOUTH_EAST, SOUTH | EAST),
+        (SOUTH_WEST, SOUTH | WEST),
+        (NORTH_WEST, NORTH | WEST),
+    ];
+    let mut i = 0;
+    while i < corners.len() {
+        let (corner, sides) = corners[i];
+        if mask & sides != sides {
+            mask &= !corner;
+        }
+        i += 1;
+    }
+    mask
+}
+
+const BLOB_VARIANTS: [u8; 256] = blob_variants();
+
+const fn blob_variants() -> [u8; 256] {
+    let mut variants = [0; 256];
+    let mut next = 0;
+    let mut mask = 0;
+    while mask < 256 {
+        if reduce(mask as u8) == mask as u8 {
+            variants[mask] = next;
+            next += 1;
+        }
+        mask += 1;
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_need_both_sides() {
+        let blob = |mask| TilesetLayout::Blob.variant(mask);
+        let sides = [
+            (NORTH_EAST, NORTH, EAST),
+            (SOUTH_EAST, SOUTH, EAST),
+            (SOUTH_WEST, SOUTH, WEST),
+            (NORTH_WEST, NORTH, WEST),
+        ];
+        for (corner, a, b) in sides {
+            assert_eq!(blob(corner), blob(0));
+            assert_eq!(blob(corner | a), blob(a));
+            assert_eq!(blob(corner | b), blob(b));
+            assert_ne!(blob(corner | a | b), blob(a | b));
+        }
+        assert_eq!(
+            blob(!NORTH),
+            blob(EAST | SOUTH_EAST | SOUTH | SOUTH_WEST | WEST)
+        );
+    }
+
+    #[test]
+    fn every_blob_variant_is_used() {
+        let mut used = (0..=255)
+            .map(|mask| TilesetLayout::Blob.variant(mask))
+            .collect::<Vec<_>>();
+        used.sort();
+        used.dedup();
+        assert_eq!(used, (0..47).collect::<Vec<_>>());
+        let (columns, rows) = TilesetLayout::Blob.grid();
+        assert!(columns * rows >= 47);
+    }
+
+    #[test]
+    fn marching_squares_ignore_corners() {
+        let variant = |mask| TilesetLayout::MarchingSquares.variant(mask);
+        assert_eq!(variant(NORTH | NORTH_WEST | WEST), 1 | 8);
+        assert_eq!(variant(0xff), 15);
+        assert_eq!(variant(NORTH_EAST | SOUTH_WEST), 0);
+    }
+}
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{deposits::default_deposits, tiles::tile_for, DepositRule, HeightBand, TileType};
+
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum Biome {
+    Tundra,
+    Taiga,
+    Grassland,
+    Woodland,
+    Desert,
+    Savanna,
+    Rainforest,
+}
+
+/// A prop scattered over a biome's `tiles`, keeping at least `spacing` tiles between any two of
+/// them. Tiles steeper than `max_slope` (see [`NoiseGenerator::slope`]) are skipped.
+///
+/// [`NoiseGenerator::slope`]: crate::NoiseGenerator::slope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecorationRule {
+    pub prop: String,
+    pub spacing: f32,
+    pub tiles: Vec<TileType>,
+    #[serde(default)]
+    pub max_slope: Option<f32>,
+}
+
+impl DecorationRule {
+    fn new(prop: &str, spacing: f32, tiles: &[TileType]) -> Self {
+        Self {
+            prop: prop.into(),
+            spacing,
+            tiles: tiles.to_vec(),
+            max_slope: None,
+        }
+    }
+
+    fn max_slope(mut self, max: f32) -> Self {
+        self.max_slope = Some(max);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiomeInfo {
+    /// Sorted by `max`, see [`HeightBand`].
+    pub bands: Vec<HeightBand>,
+    pub decorations: Vec<DecorationRule>,
+    /// Checked in order, the first rule that matches a tile decides its deposit.
+    #[serde(default)]
+    pub deposits: Vec<DepositRule>,
+}
+
+impl BiomeInfo {
+    pub fn tile_for(&self, height: f32) -> TileType {
+        tile_for(&self.bands, height)
+    }
+}
+
+/// Whittaker style lookup. Rows go from cold to hot, columns from dry to wet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiomeTable {
+    pub rows: Vec<Vec<Biome>>,
+    /// Fraction of a cell on either side of a border that gets blended, from 0 to 0.5.
+    pub blend: f32,
+}
+
+impl BiomeTable {
+    /// Biomes around `(temperature, moisture)` with weights that sum to 1.
+    pub fn weights(&self, temperature: f32, moisture: f32) -> Vec<(Biome, f32)> {
+        let (t_lo, t_hi, t) = self.axis(temperature, self.rows.len());
+        let columns = self.rows.first().map_or(0, Vec::len);
+        let (m_lo, m_hi, m) = self.axis(moisture, columns);
+
+        let mut weights = Vec::with_capacity(4);
+        for (row, row_weight) in [(t_lo, 1. - t), (t_hi, t)] {
+            for (column, column_weight) in [(m_lo, 1. - m), (m_hi, m)] {
+                let weight = row_weight * column_weight;
+                if weight <= 0. {
+                    continue;
+                }
+                let biome = self.rows[row][column];
+                match weights.iter_mut().find(|(b, _)| *b == biome) {
+                    Some((_, w)) => *w += weight,
+                    None => weights.push((biome, weight)),
+                }
+            }
+        }
+        weights
+    }
+
+    /// Neighbouring cells along one axis and how far `value` has blended into the upper one.
+    fn axis(&self, value: f32, cells: usize) -> (usize, usize, f32) {
+        let last = cells.saturating_sub(1);
+        let pos = value.clamp(0., 1.) * cells as f32 - 0.5;
+        let lo = (pos.floor().max(0.) as usize).min(last);
+        let hi = (lo + 1).min(last);
+        let frac = pos - pos.floor();
+        let blend = self.blend.clamp(0.001, 0.5);
+        let t = if pos < 0. || lo == hi {
+            0.
+        } else {
+            smoothstep(0.5 - blend, 0.5 + blend, frac)
+        };
+        (lo, hi, t)
+    }
+
+    /// Picks one biome out of [`Self::weights`], `dither` in `0..1` decides where borders mix.
+    pub fn pick(&self, temperature: f32, moisture: f32, dither: f32) -> Biome {
+        let weights = self.weights(temperature, moisture);
+        let mut acc = 0.;
+        for (biome, weight) in &weights {
+            acc += weight;
+            if dither < acc {
+                return *biome;
+            }
+        }
+        weights.last().map_or(Biome::Grassland, |(biome, _)| *biome)
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+pub(crate) fn default_table() -> BiomeTable {
+    use Biome::*;
+    BiomeTable {
+        rows: vec![
+            vec![Tundra, Taiga, Taiga],
+            vec![Grassland, Grassland, Woodland],
+            vec![Desert, Savanna, Rainforest],
+        ],
+        blend: 0.15,
+    }
+}
+
+/// Trees don't grow on the steepest forest tiles, which leaves clearings near ridges.
+const TREE_SLOPE: f32 = 0.06;
+
+pub(crate) fn default_biomes() -> Vec<(Biome, BiomeInfo)> {
+    use TileType::*;
+    let palette = |land: &[(f32, TileType)]| {
+        let water = [(0.35, DeepWater), (0.42, ShallowWater)];
+        water
+            .iter()
+            .chain(land)
+            .map(|(max, tile)| HeightBand {
+                max: *max,
+                tile: *tile,
+            })
+            .collect()
+    };
+    let rule = DecorationRule::new;
+
+    vec![
+        (
+            Biome::Tundra,
+            BiomeInfo {
+                bands: palette(&[(0.46, Sand), (0.62, Snow), (0.76, Rock), (1., Snow)]),
+                decorations: vec![rule("rock", 6., &[Snow])],
+                deposits: default_deposits(Biome::Tundra),
+            },
+        ),
+        (
+            Biome::Taiga,
+            BiomeInfo {
+                bands: palette(&[
+                    (0.46, Sand),
+                    (0.56, Grass),
+                    (0.68, Forest),
+                    (0.76, Rock),
+                    (1., Snow),
+                ]),
+                decorations: vec![
+                    rule("pine", 2., &[Forest]).max_slope(TREE_SLOPE),
+                    rule("rock", 6., &[Grass]),
+                ],
+                deposits: default_deposits(Biome::Taiga),
+            },
+        ),
+        (
+            Biome::Grassland,
+            BiomeInfo {
+                bands: palette(&[
+                    (0.46, Sand),
+                    (0.64, Grass),
+                    (0.70, Forest),
+                    (0.78, Rock),
+                    (1., Snow),
+                ]),
+                decorations: vec![
+                    rule("flower", 3., &[Grass]),
+                    rule("tree", 3., &[Forest]).max_slope(TREE_SLOPE),
+                ],
+                deposits: default_deposits(Biome::Grassland),
+            },
+        ),
+        (
+            Biome::Woodland,
+            BiomeInfo {
+                bands: palette(&[
+                    (0.46, Sand),
+                    (0.52, Grass),
+                    (0.70, Forest),
+                    (0.78, Rock),
+                    (1., Snow),
+                ]),
+                decorations: vec![
+                    rule("tree", 1.8, &[Forest]).max_slope(TREE_SLOPE),
+                    rule("flower", 4.5, &[Grass]),
+                ],
+                deposits: default_deposits(Biome::Woodland),
+            },
+        ),
+        (
+            Biome::Desert,
+            BiomeInfo {
+                bands: palette(&[(0.68, Sand), (1., Rock)]),
+                decorations: vec![rule("cactus", 5., &[Sand])],
+                deposits: default_deposits(Biome::Desert),
+            },
+        ),
+        (
+            Biome::Savanna,
+            BiomeInfo {
+                bands: palette(&[(0.50, Sand), (0.70, Grass), (1., Rock)]),
+                decorations: vec![
+                    rule("tree", 6., &[Grass]).max_slope(TREE_SLOPE),
+                    rule("rock", 8., &[Sand]),
+                ],
+                deposits: default_deposits(Biome::Savanna),
+            },
+        ),
+        (
+            Biome::Rainforest,
+            BiomeInfo {
+                bands: palette(&[(0.44, Sand), (0.74, Forest), (0.80, Rock), (1., Snow)]),
+                decorations: vec![
+                    rule("tree", 1.5, &[Forest]).max_slope(TREE_SLOPE),
+                    rule("flower", 3., &[Forest]),
+                ],
+                deposits: default_deposits(Biome::Rainforest),
+            },
+        ),
+    ]
+}
@@ -0,0 +1,178 @@
+use bevy::math::IVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash_unit, regions::Grid, ChunkCoord, ChunkData, NoiseGenerator, TerrainConfig,
+    TerrainGenerator, TileType, CHUNK_X, CHUNK_Y,
+};
+
+/// Settings for [`CaveGenerator`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaveConfig {
+    /// Chance of a tile starting out as wall.
+    pub fill: f32,
+    pub iterations: u32,
+    /// Open tiles with at least this many walls around them become wall.
+    pub birth: u8,
+    /// Walls with at least this many walls around them stay wall.
+    pub survival: u8,
+    /// Open regions smaller than this are pockets, handled according to `pockets`.
+    pub min_region: u32,
+    pub pockets: Pockets,
+    pub wall: TileType,
+    pub floor: TileType,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            fill: 0.45,
+            iterations: 5,
+            birth: 5,
+            survival: 4,
+            min_region: 24,
+            pockets: Pockets::Connect { max_tunnel: 12 },
+            wall: TileType::Rock,
+            floor: TileType::Sand,
+        }
+    }
+}
+
+/// What happens to open regions too small to be part of the cave system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pockets {
+    Keep,
+    Fill,
+    /// Digs a tunnel to the nearest bigger region, pockets with nothing in reach are filled.
+    Connect {
+        max_tunnel: u32,
+    },
+}
+
+/// Caves from cellular automata smoothing of random noise. Biomes still come from the climate
+/// noise of the [`TerrainConfig`].
+pub struct CaveGenerator {
+    seed: u32,
+    caves: CaveConfig,
+    noise: NoiseGenerator,
+}
+
+impl CaveGenerator {
+    pub fn new(seed: u32, config: &TerrainConfig) -> Self {
+        Self {
+            seed,
+            caves: config.caves.clone(),
+            noise: NoiseGenerator::new(seed, config),
+        }
+    }
+
+    /// How far around the chunk pockets are looked at. A pocket touching the chunk lies within
+    /// `min_region` of it, its tunnel within another `max_tunnel`, and the region the tunnel
+    /// ends in has to be fully visible to tell whether it's a pocket itself. Anything decided
+    /// inside this margin comes out the same as it would for an endless grid, so neighbouring
+    /// chunks agree on their shared edges.
+    fn margin(&self) -> i32 {
+        let tunnel = match self.caves.pockets {
+            Pockets::Keep => return 0,
+            Pockets::Fill => 0,
+            Pockets::Connect { max_tunnel } => max_tunnel,
+        };
+        (2 * (self.caves.min_region + tunnel)) as i32
+    }
+
+    /// The walls around `coord` after smoothing and handling pockets.
+    fn grid(&self, coord: ChunkCoord) -> Grid {
+        let margin = self.margin();
+        // every smoothing pass leaves the outermost ring wrong, so pad for those as well
+        let padding = margin + self.caves.iterations as i32;
+        let origin = coord.origin().as_ivec2();
+        let mut grid = Grid::new(
+            origin - padding,
+            IVec2::new(CHUNK_X, CHUNK_Y) + 2 * padding,
+            |pos| hash_unit(self.seed, pos.x, pos.y) < self.caves.fill,
+        );
+        for _ in 0..self.caves.iterations {
+            grid = grid.smooth(self.caves.birth, self.caves.survival);
+        }
+
+        let window = (
+            origin - margin,
+            origin + IVec2::new(CHUNK_X, CHUNK_Y) + margin,
+        );
+        match self.caves.pockets {
+            Pockets::Keep => {}
+            Pockets::Fill => {
+                for pocket in grid.pockets(window, self.caves.min_region) {
+                    grid.set_all(&pocket, true);
+                }
+            }
+            Pockets::Connect { max_tunnel } => {
+                let pockets = grid.pockets(window, self.caves.min_region);
+                let small = pockets.iter().flatten().copied().collect();
+                let tunnels = pockets
+                    .iter()
+                    .map(|pocket| grid.tunnel(window, pocket, &small, max_tunnel))
+                    .collect::<Vec<_>>();
+                // fill first so a tunnel through a neighbouring pocket stays open
+                for (pocket, tunnel) in pockets.iter().zip(&tunnels) {
+                    if tunnel.is_none() {
+                        grid.set_all(pocket, true);
+                    }
+                }
+                for tunnel in tunnels.iter().flatten() {
+                    grid.set_all(tunnel, false);
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl TerrainGenerator for CaveGenerator {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+        let grid = self.grid(coord);
+        ChunkData::from_fn(coord, |pos| {
+            let biome = self.noise.biome(pos);
+            if grid.is_wall(pos.as_ivec2()) {
+                (self.caves.wall, 1., biome)
+            } else {
+                (self.caves.floor, 0., biome)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The walls of a chunk's window that lie in its neighbours match those neighbours.
+    fn seams_match(pockets: Pockets) {
+        let mut config = TerrainConfig::default();
+        config.caves.pockets = pockets;
+        let caves = CaveGenerator::new(7, &config);
+        let center = ChunkCoord::new(-1, 2);
+        let grid = caves.grid(center);
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let coord = center.offset(x, y);
+                let data = caves.generate_chunk(coord);
+                for pos in coord.tiles() {
+                    let wall = data.tile(pos.local()) == config.caves.wall;
+                    assert_eq!(grid.is_wall(pos.as_ivec2()), wall, "{pos:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filled_pockets_match_across_seams() {
+        seams_match(Pockets::Fill);
+    }
+
+    #[test]
+    fn tunnels_match_across_seams() {
+        seams_match(Pockets::Connect { max_tunnel: 12 });
+    }
+}
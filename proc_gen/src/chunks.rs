@@ -0,0 +1,165 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+
+use crate::{ChunkCoord, ChunkData, Generator, TerrainEdits, TerrainMap};
+
+/// Marks an entity (usually the camera or the player) that keeps chunks loaded around it.
+#[derive(Component, Default)]
+pub struct ChunkLoader;
+
+#[derive(Component)]
+pub struct Chunk(pub ChunkCoord);
+
+/// Radii are in chunks. Chunks load inside `load_radius` and only unload once they are
+/// further than `unload_radius`, so walking back and forth over a border doesn't thrash.
+/// `spawn_budget` caps how many generated chunks are spawned each frame.
+#[derive(Resource)]
+pub struct ChunkSettings {
+    pub load_radius: i32,
+    pub unload_radius: i32,
+    pub spawn_budget: usize,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        Self {
+            load_radius: 3,
+            unload_radius: 5,
+            spawn_budget: 4,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LoadedChunks(HashMap<ChunkCoord, Entity>);
+
+impl LoadedChunks {
+    pub fn get(&self, coord: ChunkCoord) -> Option<Entity> {
+        self.0.get(&coord).copied()
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.0.contains_key(&coord)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ChunkCoord, Entity)> + '_ {
+        self.0.iter().map(|(coord, entity)| (*coord, *entity))
+    }
+
+    pub(crate) fn clear(&mut self, commands: &mut Commands) {
+        for (_, entity) in self.0.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Chunks still being generated on the async compute pool.
+#[derive(Resource, Default)]
+pub struct PendingChunks(HashMap<ChunkCoord, Task<ChunkData>>);
+
+impl PendingChunks {
+    pub fn is_pending(&self, coord: ChunkCoord) -> bool {
+        self.0.contains_key(&coord)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+fn within(a: ChunkCoord, b: ChunkCoord, radius: i32) -> bool {
+    (a.as_ivec2() - b.as_ivec2()).length_squared() <= radius * radius
+}
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    settings: Res<ChunkSettings>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut pending: ResMut<PendingChunks>,
+    mut map: ResMut<TerrainMap>,
+    loaders: Query<&GlobalTransform, With<ChunkLoader>>,
+    generator: Res<Generator>,
+) {
+    let centres = loaders
+        .iter()
+        .map(|transform| ChunkCoord::from_world(transform.translation().truncate()))
+        .collect::<Vec<_>>();
+
+    loaded.0.retain(|coord, entity| {
+        let keep = centres
+            .iter()
+            .any(|centre| within(*coord, *centre, settings.unload_radius));
+        if !keep {
+            commands.entity(*entity).despawn_recursive();
+            map.remove_chunk(*coord);
+        }
+        keep
+    });
+    // dropping a task cancels it
+    pending.0.retain(|coord, _| {
+        centres
+            .iter()
+            .any(|centre| within(*coord, *centre, settings.unload_radius))
+    });
+
+    let pool = AsyncComputeTaskPool::get();
+
+    let radius = settings.load_radius;
+    for centre in centres {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let coord = centre.offset(x, y);
+                if !within(coord, centre, radius)
+                    || loaded.is_loaded(coord)
+                    || pending.is_pending(coord)
+                {
+                    continue;
+                }
+                let generator = generator.0.clone();
+                let task = pool.spawn(async move { generator.generate_chunk(coord) });
+                pending.0.insert(coord, task);
+            }
+        }
+    }
+}
+
+pub fn spawn_ready_chunks(
+    mut commands: Commands,
+    settings: Res<ChunkSettings>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut pending: ResMut<PendingChunks>,
+    mut map: ResMut<TerrainMap>,
+    edits: Res<TerrainEdits>,
+) {
+    let ready = pending
+        .0
+        .iter()
+        .filter(|(_, task)| task.is_finished())
+        .map(|(coord, _)| *coord)
+        .take(settings.spawn_budget)
+        .collect::<Vec<_>>();
+
+    for coord in ready {
+        let Some(task) = pending.0.remove(&coord) else {
+            continue;
+        };
+        let mut data = block_on(task);
+        edits.apply(&mut data);
+        let entity = spawn_chunk(&mut commands, data.coord);
+        map.insert_chunk(data);
+        loaded.0.insert(coord, entity);
+    }
+}
+
+fn spawn_chunk(commands: &mut Commands, coord: ChunkCoord) -> Entity {
+    commands
+        .spawn((
+            Chunk(coord),
+            Transform::from_translation(coord.to_world().extend(0.)),
+            Visibility::default(),
+        ))
+        .id()
+}
@@ -0,0 +1,229 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{ChunkChanged, ChunkCoord, TerrainMap, TileChanged, BLOCK_SIZE, CHUNK_X, CHUNK_Y};
+
+/// How far bodies are kept from the surfaces they slide along, so the next sweep doesn't start
+/// touching them.
+const SKIN: f32 = 0.01;
+/// Each slide removes the motion along one axis, so a few iterations are always enough.
+const MAX_SLIDES: usize = 3;
+
+/// Solid tiles of every loaded chunk, merged into as few world space rectangles as possible.
+#[derive(Resource, Default)]
+pub struct TerrainColliders {
+    chunks: HashMap<ChunkCoord, Vec<Rect>>,
+}
+
+/// First contact of a swept box. `time` is the fraction of the motion covered before touching.
+#[derive(Debug, Clone, Copy)]
+pub struct SweepHit {
+    pub time: f32,
+    pub normal: Vec2,
+    pub collider: Rect,
+}
+
+impl TerrainColliders {
+    pub fn chunk(&self, coord: ChunkCoord) -> &[Rect] {
+        self.chunks.get(&coord).map_or(&[], Vec::as_slice)
+    }
+
+    /// Colliders of the chunks touching `area`.
+    pub fn near(&self, area: Rect) -> impl Iterator<Item = Rect> + '_ {
+        let min = ChunkCoord::from_world(area.min);
+        let max = ChunkCoord::from_world(area.max);
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| ChunkCoord::new(x, y)))
+            .flat_map(|coord| self.chunk(coord).iter().copied())
+    }
+
+    pub fn overlaps_aabb(&self, center: Vec2, half_size: Vec2) -> bool {
+        let area = Rect::from_center_half_size(center, half_size);
+        self.near(area)
+            .any(|collider| !collider.intersect(area).is_empty())
+    }
+
+    pub fn overlaps_circle(&self, center: Vec2, radius: f32) -> bool {
+        let area = Rect::from_center_half_size(center, Vec2::splat(radius));
+        self.near(area).any(|collider| {
+            let closest = center.clamp(collider.min, collider.max);
+            closest.distance_squared(center) < radius * radius
+        })
+    }
+
+    /// Moves a box centered on `center` by `motion` and returns where it first touches a
+    /// collider. Colliders the box already overlaps are ignored so it can always move out.
+    pub fn sweep_aabb(&self, center: Vec2, half_size: Vec2, motion: Vec2) -> Option<SweepHit> {
+        let start = Rect::from_center_half_size(center, half_size);
+        let end = Rect::from_center_half_size(center + motion, half_size);
+        self.near(start.union(end))
+            .filter_map(|collider| sweep(center, half_size, motion, collider))
+            .min_by(|a, b| a.time.total_cmp(&b.time))
+    }
+
+    /// Moves a box by `motion`, sliding along any colliders in the way, and returns its new
+    /// center.
+    pub fn move_and_slide(&self, center: Vec2, half_size: Vec2, motion: Vec2) -> Vec2 {
+        let mut center = center;
+        let mut motion = motion;
+        for _ in 0..MAX_SLIDES {
+            if motion == Vec2::ZERO {
+                break;
+            }
+            let Some(hit) = self.sweep_aabb(center, half_size, motion) else {
+                return center + motion;
+            };
+            center += motion * hit.time + hit.normal * SKIN;
+            motion *= 1. - hit.time;
+            motion -= hit.normal * motion.dot(hit.normal);
+        }
+        center
+    }
+}
+
+/// Swept box against one rectangle, done as a ray against the rectangle grown by the box.
+fn sweep(center: Vec2, half_size: Vec2, motion: Vec2, collider: Rect) -> Option<SweepHit> {
+    let grown = Rect::from_corners(collider.min - half_size, collider.max + half_size);
+    if grown.contains(center) && !on_edge(grown, center) {
+        return None;
+    }
+
+    let mut entry = Vec2::splat(f32::NEG_INFINITY);
+    let mut exit = Vec2::splat(f32::INFINITY);
+    for axis in 0..2 {
+        if motion[axis] == 0. {
+            if center[axis] <= grown.min[axis] || center[axis] >= grown.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let a = (grown.min[axis] - center[axis]) / motion[axis];
+        let b = (grown.max[axis] - center[axis]) / motion[axis];
+        entry[axis] = a.min(b);
+        exit[axis] = a.max(b);
+    }
+
+    let time = entry.max_element();
+    if time > exit.min_element() || !(0. ..=1.).contains(&time) {
+        return None;
+    }
+    let normal = if entry.x > entry.y {
+        Vec2::new(-motion.x.signum(), 0.)
+    } else {
+        Vec2::new(0., -motion.y.signum())
+    };
+    // Moving away from a surface the box is resting on isn't a hit.
+    if motion.dot(normal) >= 0. {
+        return None;
+    }
+    Some(SweepHit {
+        time,
+        normal,
+        collider,
+    })
+}
+
+fn on_edge(rect: Rect, point: Vec2) -> bool {
+    point.x == rect.min.x || point.x == rect.max.x || point.y == rect.min.y || point.y == rect.max.y
+}
+
+/// Greedily merges the solid tiles of a chunk into rectangles: each run along a row is grown
+/// upwards for as long as the rows above are solid over the same span.
+fn merge_solid(map: &TerrainMap, coord: ChunkCoord) -> Vec<Rect> {
+    let (width, height) = (CHUNK_X as usize, CHUNK_Y as usize);
+    let mut open: Vec<bool> = coord.tiles().map(|pos| map.is_solid(pos)).collect();
+    let mut rects = Vec::new();
+
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            if !open[y * width + x] {
+                x += 1;
+                continue;
+            }
+            let run = (x..width).take_while(|&i| open[y * width + i]).count();
+            let rows = (y..height)
+                .take_while(|&j| (x..x + run).all(|i| open[j * width + i]))
+                .count();
+            for j in y..y + rows {
+                open[j * width..][x..x + run].fill(false);
+            }
+
+            let min = coord.tile(IVec2::new(x as i32, y as i32)).to_world();
+            let size = Vec2::new(run as f32, rows as f32) * BLOCK_SIZE;
+            rects.push(Rect::from_corners(min, min + size));
+            x += run;
+        }
+    }
+    rects
+}
+
+pub fn update_colliders(
+    mut changes: EventReader<ChunkChanged>,
+    mut edits: EventReader<TileChanged>,
+    map: Res<TerrainMap>,
+    mut colliders: ResMut<TerrainColliders>,
+) {
+    let edited = edits
+        .read()
+        .map(|edit| edit.pos.chunk())
+        .collect::<HashSet<_>>();
+    let changed = changes.read().map(|ChunkChanged(coord)| *coord);
+    for coord in edited.into_iter().chain(changed) {
+        if map.is_loaded(coord.origin()) {
+            colliders.chunks.insert(coord, merge_solid(&map, coord));
+        } else {
+            colliders.chunks.remove(&coord);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A wall from x 50 to 60 in the first chunk.
+    fn wall() -> TerrainColliders {
+        let rect = Rect::new(50., 0., 60., 100.);
+        TerrainColliders {
+            chunks: HashMap::from_iter([(ChunkCoord::new(0, 0), vec![rect])]),
+        }
+    }
+
+    #[test]
+    fn sweep_stops_at_the_wall() {
+        let hit = wall()
+            .sweep_aabb(Vec2::new(10., 50.), Vec2::splat(5.), Vec2::new(100., 0.))
+            .unwrap();
+        assert!((hit.time - 0.35).abs() < 1e-5);
+        assert_eq!(hit.normal, Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn sweep_misses_past_the_end_and_going_away() {
+        let colliders = wall();
+        let half_size = Vec2::splat(5.);
+        let above = Vec2::new(10., 120.);
+        assert!(colliders
+            .sweep_aabb(above, half_size, Vec2::new(100., 0.))
+            .is_none());
+        // resting against the wall and moving off it
+        let touching = Vec2::new(45., 50.);
+        assert!(colliders
+            .sweep_aabb(touching, half_size, Vec2::new(-10., 0.))
+            .is_none());
+        // already inside, free to get out
+        assert!(colliders
+            .sweep_aabb(Vec2::new(55., 50.), half_size, Vec2::new(20., 0.))
+            .is_none());
+    }
+
+    #[test]
+    fn slides_along_the_wall() {
+        let end = wall().move_and_slide(Vec2::new(10., 20.), Vec2::splat(5.), Vec2::new(100., 40.));
+        assert!(end.x < 45. && end.x > 44.9, "{end}");
+        assert!((end.y - 60.).abs() < 1e-3, "{end}");
+    }
+}
@@ -0,0 +1,177 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::{self, Biome, BiomeInfo, BiomeTable},
+    noise_graph::NoiseNode,
+    structures, CaveConfig, ConnectivityConfig, ErosionConfig, Mask, RiverConfig, Structure,
+    TileInfo, TileType, TilesetLayout, WfcConfig,
+};
+
+/// Everything that decides what the world looks like apart from the seed. Can be loaded from a
+/// `.terrain.ron` file by inserting a [`TerrainConfigHandle`], missing fields use the defaults.
+#[derive(Resource, Asset, TypePath, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    /// Height in roughly `-1..1`, mapped to `0..1` before it goes through the biome palettes.
+    pub height: NoiseNode,
+    pub temperature: NoiseNode,
+    pub moisture: NoiseNode,
+    /// Shape of the resource veins, see [`DepositRule`](crate::DepositRule).
+    pub veins: NoiseNode,
+    pub biomes: HashMap<Biome, BiomeInfo>,
+    pub table: BiomeTable,
+    /// Multiplied into the height for a world with coasts, see [`Mask`].
+    pub mask: Option<Mask>,
+    /// Wears the height down with droplets running downhill, off when `None`.
+    pub erosion: Option<ErosionConfig>,
+    /// Rivers from the mountains to the sea, off when `None`.
+    pub rivers: Option<RiverConfig>,
+    pub tiles: HashMap<TileType, TileInfo>,
+    pub structures: Vec<Structure>,
+    /// Only used by [`CaveGenerator`](crate::CaveGenerator).
+    pub caves: CaveConfig,
+    /// Only used by [`WfcGenerator`](crate::WfcGenerator).
+    pub wfc: WfcConfig,
+    /// Only used by [`Connected`](crate::Connected).
+    pub connectivity: ConnectivityConfig,
+}
+
+impl TerrainConfig {
+    pub fn tile_for(&self, biome: Biome, height: f32) -> TileType {
+        self.biomes
+            .get(&biome)
+            .map_or(TileType::Grass, |info| info.tile_for(height))
+    }
+
+    pub fn info(&self, tile: TileType) -> Option<&TileInfo> {
+        self.tiles.get(&tile)
+    }
+
+    /// Catches mistakes in hand written configs that would otherwise bring down the generator.
+    pub fn validate(&self) -> Result<(), String> {
+        let rows = &self.table.rows;
+        let columns = rows.first().map_or(0, Vec::len);
+        if columns == 0 {
+            return Err("the biome table is empty".into());
+        }
+        if let Some(row) = rows.iter().position(|row| row.len() != columns) {
+            return Err(format!(
+                "row {row} of the biome table has {} biomes, the first has {columns}",
+                rows[row].len()
+            ));
+        }
+
+        if let Some(Mask::Gradient {
+            image: Some(image), ..
+        }) = &self.mask
+        {
+            image.validate()?;
+        }
+
+        let rivers = self
+            .rivers
+            .as_ref()
+            .map(|rivers| ("rivers", rivers.spacing));
+        let spacings = self
+            .biomes
+            .values()
+            .flat_map(|info| &info.decorations)
+            .map(|rule| (rule.prop.as_str(), rule.spacing))
+            .chain(self.structures.iter().map(|s| (s.name.as_str(), s.spacing)))
+            .chain(rivers);
+        for (name, spacing) in spacings {
+            if spacing.is_nan() || spacing <= 0. {
+                return Err(format!(
+                    "{name} has a spacing of {spacing}, it must be positive"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            height: NoiseNode::perlin(0)
+                .fbm(4, 2., 0.5)
+                .warp(NoiseNode::perlin(3).scale(0.5), 0.6)
+                .scale(1. / 24.),
+            temperature: NoiseNode::perlin(1).fbm(2, 2., 0.4).scale(1. / 80.),
+            moisture: NoiseNode::perlin(2).fbm(2, 2., 0.4).scale(1. / 80.),
+            veins: NoiseNode::perlin(4).fbm(2, 2., 0.5).scale(1. / 16.),
+            biomes: biome::default_biomes().into_iter().collect(),
+            table: biome::default_table(),
+            mask: None,
+            erosion: None,
+            rivers: None,
+            tiles: HashMap::from_iter([
+                (
+                    TileType::DeepWater,
+                    TileInfo::new("deep_water.png", false, true)
+                        .with_autotile("deep_water_tiles.png", TilesetLayout::MarchingSquares),
+                ),
+                (
+                    TileType::ShallowWater,
+                    TileInfo::new("shallow_water.png", false, false),
+                ),
+                (TileType::Sand, TileInfo::new("sand.png", true, false)),
+                (TileType::Grass, TileInfo::new("grass.png", true, false)),
+                (
+                    TileType::Forest,
+                    TileInfo::new("forest.png", true, false).with_dig_to(TileType::Grass),
+                ),
+                (
+                    TileType::Rock,
+                    TileInfo::new("rock.png", false, true)
+                        .with_autotile("rock_tiles.png", TilesetLayout::Blob)
+                        .with_dig_to(TileType::Sand),
+                ),
+                (TileType::Snow, TileInfo::new("snow.png", true, false)),
+            ]),
+            structures: structures::default_structures(),
+            caves: CaveConfig::default(),
+            wfc: WfcConfig::default(),
+            connectivity: ConnectivityConfig::default(),
+        }
+    }
+}
+
+/// When present, the [`TerrainConfig`] resource follows this asset and the world is regenerated
+/// whenever it (re)loads.
+#[derive(Resource)]
+pub struct TerrainConfigHandle(pub Handle<TerrainConfig>);
+
+#[derive(Default)]
+pub struct TerrainConfigLoader;
+
+impl AssetLoader for TerrainConfigLoader {
+    type Asset = TerrainConfig;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<TerrainConfig, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut config: TerrainConfig = ron::de::from_bytes(&bytes)?;
+        config.validate()?;
+        if let Some(mask) = &mut config.mask {
+            mask.load(load_context).await?;
+        }
+        Ok(config)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["terrain.ron"]
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    math::IVec2,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    regions::Grid, ChunkCoord, ChunkData, TerrainConfig, TerrainGenerator, TilePos, TileType,
+    CHUNK_X, CHUNK_Y,
+};
+
+/// Settings for [`Connected`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectivityConfig {
+    /// Walkable regions smaller than this are pockets, handled according to `pockets`.
+    pub min_region: u32,
+    pub pockets: Unreachable,
+    /// What blocked tiles along a corridor become.
+    pub corridor: TileType,
+}
+
+impl Default for ConnectivityConfig {
+    fn default() -> Self {
+        Self {
+            min_region: 40,
+            pockets: Unreachable::Connect { max_corridor: 10 },
+            corridor: TileType::Sand,
+        }
+    }
+}
+
+/// What happens to walkable regions too small to be part of the wider world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unreachable {
+    /// Leaves pockets as they are but marks them unreachable.
+    Mark,
+    /// Carves a corridor to the nearest bigger region, pockets with nothing in reach are marked.
+    Connect { max_corridor: u32 },
+}
+
+/// Inner chunks kept around for the windows of the chunks next to them.
+const CACHE_SIZE: usize = 4096;
+
+/// Wraps another generator and makes sure walkable tiles aren't stuck in small pockets, see
+/// [`ConnectivityConfig`]. Tiles it gives up on are marked, see [`ChunkData::is_reachable`].
+pub struct Connected<G> {
+    inner: G,
+    connectivity: ConnectivityConfig,
+    walkable: HashSet<TileType>,
+    cache: Mutex<HashMap<ChunkCoord, Arc<ChunkData>>>,
+}
+
+impl<G: TerrainGenerator> Connected<G> {
+    pub fn new(inner: G, config: &TerrainConfig) -> Self {
+        Self {
+            inner,
+            connectivity: config.connectivity.clone(),
+            walkable: config
+                .tiles
+                .iter()
+                .filter(|(_, info)| info.walkable)
+                .map(|(tile, _)| *tile)
+                .collect(),
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Same reasoning as for caves: a pocket touching the chunk lies within `min_region` of it,
+    /// its corridor within another `max_corridor`, and the region the corridor ends in has to
+    /// be fully visible to tell whether it's a pocket itself.
+    fn margin(&self) -> i32 {
+        let min_region = self.connectivity.min_region as i32;
+        match self.connectivity.pockets {
+            Unreachable::Mark => min_region,
+            Unreachable::Connect { max_corridor } => 2 * (min_region + max_corridor as i32),
+        }
+    }
+
+    /// Neighbouring chunks share most of their windows, so inner chunks are cached instead of
+    /// being generated over and over.
+    fn inner_chunk(&self, coord: ChunkCoord) -> Arc<ChunkData> {
+        if let Some(chunk) = self.cache.lock().unwrap().get(&coord) {
+            return chunk.clone();
+        }
+        let chunk = Arc::new(self.inner.generate_chunk(coord));
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(coord, chunk.clone());
+        chunk
+    }
+}
+
+impl<G: TerrainGenerator> TerrainGenerator for Connected<G> {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+        let margin = self.margin();
+        let origin = coord.origin().as_ivec2();
+        let window = (
+            origin - margin,
+            origin + IVec2::new(CHUNK_X, CHUNK_Y) + margin,
+        );
+        let (first, last) = (
+            TilePos::from(window.0).chunk(),
+            TilePos::from(window.1 - 1).chunk(),
+        );
+        let chunks = (first.y..=last.y)
+            .flat_map(|y| (first.x..=last.x).map(move |x| ChunkCoord::new(x, y)))
+            .map(|coord| (coord, self.inner_chunk(coord)))
+            .collect::<HashMap<_, _>>();
+        let grid = Grid::new(window.0, window.1 - window.0, |pos| {
+            let pos = TilePos::from(pos);
+            !self
+                .walkable
+                .contains(&chunks[&pos.chunk()].tile(pos.local()))
+        });
+
+        let pockets = grid.pockets(window, self.connectivity.min_region);
+        let (unreachable, corridors) = match self.connectivity.pockets {
+            Unreachable::Mark => (pockets, Vec::new()),
+            Unreachable::Connect { max_corridor } => {
+                let small = pockets.iter().flatten().copied().collect();
+                let mut unreachable = Vec::new();
+                let mut corridors = Vec::new();
+                // any pocket a corridor passes through is close enough for one of its own
+                for pocket in pockets {
+                    match grid.tunnel(window, &pocket, &small, max_corridor) {
+                        Some(corridor) => corridors.push(corridor),
+                        None => unreachable.push(pocket),
+                    }
+                }
+                (unreachable, corridors)
+            }
+        };
+
+        let mut data = (*chunks[&coord]).clone();
+        let in_chunk = |pos: &IVec2| TilePos::from(*pos).chunk() == coord;
+        for pos in unreachable.iter().flatten().filter(|pos| in_chunk(pos)) {
+            data.mark_unreachable(TilePos::from(*pos).local());
+        }
+        let carved = corridors
+            .iter()
+            .flatten()
+            .filter(|pos| in_chunk(pos) && grid.is_wall(**pos))
+            .map(|pos| TilePos::from(*pos))
+            .collect::<HashSet<_>>();
+        for pos in &carved {
+            data.set_tile(pos.local(), self.connectivity.corridor);
+            data.set_deposit(pos.local(), None);
+        }
+        data.retain_decorations(|decoration| !carved.contains(&decoration.tile));
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biome, Deposit};
+
+    /// Open ground left of `x = -2` and a 3x3 pocket across the border of chunks `(0, 0)` and
+    /// `(1, 0)`, rock with iron in it everywhere else.
+    struct Pocket;
+
+    impl Pocket {
+        fn is_pocket(pos: TilePos) -> bool {
+            (9..=11).contains(&pos.x) && (4..=6).contains(&pos.y)
+        }
+    }
+
+    impl TerrainGenerator for Pocket {
+        fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+            let mut data = ChunkData::from_fn(coord, |pos| {
+                let open = pos.x <= -3 || Pocket::is_pocket(pos);
+                let tile = if open {
+                    TileType::Grass
+                } else {
+                    TileType::Rock
+                };
+                (tile, 0.5, Biome::Grassland)
+            });
+            for pos in coord.tiles() {
+                if data.tile(pos.local()) == TileType::Rock {
+                    let iron = Deposit {
+                        resource: "iron".into(),
+                        amount: 3,
+                    };
+                    data.set_deposit(pos.local(), Some(iron));
+                }
+            }
+            data
+        }
+    }
+
+    fn connected(pockets: Unreachable) -> Connected<Pocket> {
+        let config = TerrainConfig {
+            connectivity: ConnectivityConfig {
+                min_region: 20,
+                pockets,
+                corridor: TileType::Sand,
+            },
+            ..TerrainConfig::default()
+        };
+        Connected::new(Pocket, &config)
+    }
+
+    #[test]
+    fn pockets_are_marked_on_both_sides_of_a_border() {
+        let generator = connected(Unreachable::Mark);
+        for coord in [ChunkCoord::new(0, 0), ChunkCoord::new(1, 0)] {
+            let data = generator.generate_chunk(coord);
+            for pos in coord.tiles() {
+                assert_eq!(
+                    data.is_reachable(pos.local()),
+                    !Pocket::is_pocket(pos),
+                    "{pos:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corridors_reach_open_ground_across_borders() {
+        let generator = connected(Unreachable::Connect { max_corridor: 15 });
+        let chunks = (-1..=1)
+            .map(|x| ChunkCoord::new(x, 0))
+            .map(|coord| (coord, generator.generate_chunk(coord)))
+            .collect::<HashMap<_, _>>();
+        let walkable = |pos: TilePos| {
+            chunks
+                .get(&pos.chunk())
+                .is_some_and(|data| data.tile(pos.local()) != TileType::Rock)
+        };
+
+        let mut seen = HashSet::from_iter([TilePos::new(11, 5)]);
+        let mut stack = vec![TilePos::new(11, 5)];
+        while let Some(pos) = stack.pop() {
+            for next in [
+                pos.offset(1, 0),
+                pos.offset(-1, 0),
+                pos.offset(0, 1),
+                pos.offset(0, -1),
+            ] {
+                if walkable(next) && seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        assert!(seen.contains(&TilePos::new(-3, 5)));
+        // the iron goes with the rock the corridor went through
+        for data in chunks.values() {
+            for (local, tile) in data.tiles() {
+                assert_eq!(data.deposit(local).is_some(), tile == TileType::Rock);
+            }
+        }
+        assert!(chunks.values().all(|data| (0..CHUNK_Y)
+            .flat_map(|y| (0..CHUNK_X).map(move |x| IVec2::new(x, y)))
+            .all(|local| data.is_reachable(local))));
+    }
+}
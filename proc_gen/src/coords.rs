@@ -0,0 +1,162 @@
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{BLOCK_SIZE, CHUNK_X, CHUNK_Y};
+
+/// A tile in the world grid. Tile `(0, 0)` covers world positions `0..BLOCK_SIZE` on both axes.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct TilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A chunk of `CHUNK_X` by `CHUNK_Y` tiles. Chunk `(0, 0)` starts at tile `(0, 0)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TilePos {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The tile containing `pos`, rounding towards negative infinity.
+    pub fn from_world(pos: Vec2) -> Self {
+        (pos / BLOCK_SIZE).floor().as_ivec2().into()
+    }
+
+    /// Bottom left corner of the tile in world space.
+    pub fn to_world(self) -> Vec2 {
+        self.as_ivec2().as_vec2() * BLOCK_SIZE
+    }
+
+    pub fn center(self) -> Vec2 {
+        self.to_world() + BLOCK_SIZE / 2.
+    }
+
+    pub fn chunk(self) -> ChunkCoord {
+        ChunkCoord::new(self.x.div_euclid(CHUNK_X), self.y.div_euclid(CHUNK_Y))
+    }
+
+    /// Position inside its chunk, always in `0..CHUNK_X` and `0..CHUNK_Y`.
+    pub fn local(self) -> IVec2 {
+        IVec2::new(self.x.rem_euclid(CHUNK_X), self.y.rem_euclid(CHUNK_Y))
+    }
+
+    pub fn offset(self, x: i32, y: i32) -> Self {
+        Self::new(self.x + x, self.y + y)
+    }
+
+    pub fn as_ivec2(self) -> IVec2 {
+        IVec2::new(self.x, self.y)
+    }
+}
+
+impl ChunkCoord {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The chunk containing `pos`, rounding towards negative infinity.
+    pub fn from_world(pos: Vec2) -> Self {
+        TilePos::from_world(pos).chunk()
+    }
+
+    /// Bottom left tile of the chunk.
+    pub fn origin(self) -> TilePos {
+        TilePos::new(self.x * CHUNK_X, self.y * CHUNK_Y)
+    }
+
+    /// Bottom left corner of the chunk in world space.
+    pub fn to_world(self) -> Vec2 {
+        self.origin().to_world()
+    }
+
+    /// Converts a position local to this chunk into a world tile.
+    pub fn tile(self, local: IVec2) -> TilePos {
+        self.origin().offset(local.x, local.y)
+    }
+
+    /// Every tile in the chunk, row by row starting from the bottom left.
+    pub fn tiles(self) -> impl Iterator<Item = TilePos> {
+        let origin = self.origin();
+        (0..CHUNK_Y).flat_map(move |y| (0..CHUNK_X).map(move |x| origin.offset(x, y)))
+    }
+
+    pub fn offset(self, x: i32, y: i32) -> Self {
+        Self::new(self.x + x, self.y + y)
+    }
+
+    pub fn as_ivec2(self) -> IVec2 {
+        IVec2::new(self.x, self.y)
+    }
+}
+
+impl From<IVec2> for TilePos {
+    fn from(value: IVec2) -> Self {
+        Self::new(value.x, value.y)
+    }
+}
+
+impl From<IVec2> for ChunkCoord {
+    fn from(value: IVec2) -> Self {
+        Self::new(value.x, value.y)
+    }
+}
+
+/// The tile under a world position.
+pub fn tile_at(world_pos: Vec2) -> TilePos {
+    TilePos::from_world(world_pos)
+}
+
+pub fn chunk_of(tile: TilePos) -> ChunkCoord {
+    tile.chunk()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_to_tile_rounds_down() {
+        assert_eq!(TilePos::from_world(Vec2::new(0., 0.)), TilePos::new(0, 0));
+        assert_eq!(
+            TilePos::from_world(Vec2::new(15.9, 16.)),
+            TilePos::new(0, 1)
+        );
+        assert_eq!(
+            TilePos::from_world(Vec2::new(-0.1, -16.)),
+            TilePos::new(-1, -1)
+        );
+        assert_eq!(
+            TilePos::from_world(Vec2::new(-16.1, -32.)),
+            TilePos::new(-2, -2)
+        );
+    }
+
+    #[test]
+    fn negative_tiles_belong_to_negative_chunks() {
+        assert_eq!(TilePos::new(-1, -1).chunk(), ChunkCoord::new(-1, -1));
+        assert_eq!(TilePos::new(-10, -11).chunk(), ChunkCoord::new(-1, -2));
+        assert_eq!(TilePos::new(9, 10).chunk(), ChunkCoord::new(0, 1));
+        assert_eq!(TilePos::new(-1, -11).local(), IVec2::new(9, 9));
+        assert_eq!(
+            ChunkCoord::from_world(Vec2::new(-0.5, 160.)),
+            ChunkCoord::new(-1, 1)
+        );
+    }
+
+    #[test]
+    fn local_positions_round_trip() {
+        for x in -25..25 {
+            for y in -25..25 {
+                let tile = TilePos::new(x, y);
+                assert_eq!(tile.chunk().tile(tile.local()), tile);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::utils::HashMap;
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+
+use crate::{hash, hash_unit, noise_graph::NoiseNode, Biome, NoiseGraph, TilePos, TileType};
+
+/// Something to harvest lying on a tile, like ore, herbs or a chest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub resource: String,
+    /// How often it can be harvested before it's gone.
+    pub amount: u32,
+}
+
+/// Puts `resource` on the biome's `tiles` between `min_height` and `max_height`. Deposits come
+/// in veins that follow the lines where the [`TerrainConfig::veins`] noise crosses zero, `veins`
+/// is how far from zero still counts, and within a vein a tile gets a deposit with a chance of
+/// `density`. Each resource has its own veins, which carry on across biomes that share it.
+///
+/// [`TerrainConfig::veins`]: crate::TerrainConfig::veins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositRule {
+    pub resource: String,
+    pub tiles: Vec<TileType>,
+    #[serde(default)]
+    pub min_height: f32,
+    #[serde(default = "one")]
+    pub max_height: f32,
+    pub veins: f32,
+    #[serde(default = "one")]
+    pub density: f32,
+    #[serde(default = "one_amount")]
+    pub amount: u32,
+}
+
+fn one() -> f32 {
+    1.
+}
+
+fn one_amount() -> u32 {
+    1
+}
+
+impl DepositRule {
+    pub(crate) fn new(resource: &str, tiles: &[TileType], veins: f32, density: f32) -> Self {
+        Self {
+            resource: resource.into(),
+            tiles: tiles.to_vec(),
+            min_height: 0.,
+            max_height: 1.,
+            veins,
+            density,
+            amount: 1,
+        }
+    }
+
+    pub(crate) fn heights(mut self, min: f32, max: f32) -> Self {
+        self.min_height = min;
+        self.max_height = max;
+        self
+    }
+
+    pub(crate) fn amount(mut self, amount: u32) -> Self {
+        self.amount = amount;
+        self
+    }
+}
+
+/// The vein noise of every resource in the config.
+pub(crate) struct Veins {
+    seed: u32,
+    noise: HashMap<String, NoiseGraph>,
+}
+
+impl Veins {
+    pub(crate) fn new<'a>(
+        seed: u32,
+        shape: &NoiseNode,
+        rules: impl IntoIterator<Item = &'a DepositRule>,
+    ) -> Self {
+        let mut noise = HashMap::new();
+        for rule in rules {
+            noise
+                .entry(rule.resource.clone())
+                .or_insert_with(|| shape.build(resource_seed(seed, &rule.resource)));
+        }
+        Self { seed, noise }
+    }
+
+    /// The deposit of the first rule that matches the tile.
+    pub(crate) fn deposit(
+        &self,
+        rules: &[DepositRule],
+        pos: TilePos,
+        tile: TileType,
+        height: f32,
+    ) -> Option<Deposit> {
+        let rule = rules.iter().find(|rule| {
+            rule.tiles.contains(&tile)
+                && (rule.min_height..=rule.max_height).contains(&height)
+                && self.noise.get(&rule.resource).is_some_and(|noise| {
+                    (noise.get([pos.x as f64, pos.y as f64]) as f32).abs() < rule.veins
+                })
+                && hash_unit(resource_seed(self.seed, &rule.resource), pos.x, pos.y) < rule.density
+        })?;
+        Some(Deposit {
+            resource: rule.resource.clone(),
+            amount: rule.amount,
+        })
+    }
+}
+
+fn resource_seed(seed: u32, resource: &str) -> u32 {
+    resource
+        .bytes()
+        .enumerate()
+        .fold(seed, |h, (i, byte)| hash(h, byte as i32, i as i32))
+}
+
+/// Iron in the rock of every biome and gold only up in the mountains, chests anywhere walkable.
+pub(crate) fn default_deposits(biome: Biome) -> Vec<DepositRule> {
+    use TileType::*;
+    let rule = DepositRule::new;
+    let mut rules = vec![
+        rule("gold", &[Rock, Snow], 0.02, 0.3)
+            .heights(0.76, 1.)
+            .amount(2),
+        rule("iron", &[Rock], 0.05, 0.5).amount(4),
+    ];
+    rules.extend(match biome {
+        Biome::Tundra => vec![],
+        Biome::Taiga | Biome::Grassland | Biome::Woodland => {
+            vec![rule("herb", &[Grass, Forest], 0.04, 0.35).amount(2)]
+        }
+        Biome::Rainforest => vec![rule("herb", &[Forest], 0.06, 0.4).amount(2)],
+        Biome::Desert | Biome::Savanna => vec![rule("copper", &[Sand, Rock], 0.04, 0.3).amount(3)],
+    });
+    rules.push(rule("chest", &[Sand, Grass, Forest, Snow], 0.01, 0.02));
+    rules
+}
@@ -0,0 +1,229 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{ChunkCoord, ChunkData, Deposit, TerrainMap, TilePos, TileType};
+
+/// Every tile changed and deposit harvested at runtime, kept per chunk so the changes can be
+/// put back whenever the chunk is generated again.
+#[derive(Resource, Default, Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainEdits {
+    chunks: HashMap<ChunkCoord, HashMap<TilePos, TileType>>,
+    /// What's left of every harvested deposit.
+    #[serde(default)]
+    deposits: HashMap<ChunkCoord, HashMap<TilePos, u32>>,
+}
+
+impl TerrainEdits {
+    pub fn get(&self, pos: TilePos) -> Option<TileType> {
+        self.chunks.get(&pos.chunk())?.get(&pos).copied()
+    }
+
+    pub fn chunk(&self, coord: ChunkCoord) -> impl Iterator<Item = (TilePos, TileType)> + '_ {
+        self.chunks
+            .get(&coord)
+            .into_iter()
+            .flat_map(|tiles| tiles.iter().map(|(pos, tile)| (*pos, *tile)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.deposits.is_empty()
+    }
+
+    fn record(&mut self, pos: TilePos, tile: TileType) {
+        self.chunks
+            .entry(pos.chunk())
+            .or_default()
+            .insert(pos, tile);
+    }
+
+    fn record_harvest(&mut self, pos: TilePos, left: u32) {
+        self.deposits
+            .entry(pos.chunk())
+            .or_default()
+            .insert(pos, left);
+    }
+
+    /// Puts the edits back on a freshly generated chunk, dropping decorations and deposits on
+    /// edited tiles.
+    pub(crate) fn apply(&self, data: &mut ChunkData) {
+        for (pos, left) in self.deposits.get(&data.coord).into_iter().flatten() {
+            let deposit = data.deposit(pos.local()).map(|deposit| Deposit {
+                resource: deposit.resource.clone(),
+                amount: *left,
+            });
+            data.set_deposit(pos.local(), deposit.filter(|_| *left > 0));
+        }
+        let Some(tiles) = self.chunks.get(&data.coord) else {
+            return;
+        };
+        for (pos, tile) in tiles {
+            data.set_tile(pos.local(), *tile);
+            data.set_deposit(pos.local(), None);
+        }
+        data.retain_decorations(|decoration| !tiles.contains_key(&decoration.tile));
+    }
+}
+
+/// Sent for every loaded tile changed through a [`TerrainEditor`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TileChanged {
+    pub pos: TilePos,
+    pub old: TileType,
+    pub new: TileType,
+}
+
+/// Sent for every deposit harvested through a [`TerrainEditor`]. The deposit is gone once
+/// `left` is zero.
+#[derive(Event, Debug, Clone)]
+pub struct DepositHarvested {
+    pub pos: TilePos,
+    pub taken: Deposit,
+    pub left: u32,
+}
+
+/// Changes tiles at runtime. Edits are recorded in [`TerrainEdits`] so they outlive the chunk.
+#[derive(SystemParam)]
+pub struct TerrainEditor<'w> {
+    map: ResMut<'w, TerrainMap>,
+    edits: ResMut<'w, TerrainEdits>,
+    events: EventWriter<'w, TileChanged>,
+    harvests: EventWriter<'w, DepositHarvested>,
+}
+
+impl TerrainEditor<'_> {
+    pub fn map(&self) -> &TerrainMap {
+        &self.map
+    }
+
+    /// Changes a tile whether or not its chunk is loaded, unloaded chunks pick it up once they
+    /// are generated. Whatever deposit was on the tile is gone.
+    pub fn set_tile(&mut self, pos: TilePos, tile: TileType) {
+        self.edits.record(pos, tile);
+        self.map.clear_deposit(pos);
+        match self.map.set_tile(pos, tile) {
+            Some(old) if old != tile => {
+                self.events.send(TileChanged {
+                    pos,
+                    old,
+                    new: tile,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Turns a loaded tile into what it leaves behind when dug out (see [`TileInfo::dig_to`]).
+    /// Returns the new tile, or `None` if the tile can't be dug.
+    ///
+    /// [`TileInfo::dig_to`]: crate::TileInfo::dig_to
+    pub fn dig(&mut self, pos: TilePos) -> Option<TileType> {
+        let tile = self.map.info(pos)?.dig_to?;
+        self.set_tile(pos, tile);
+        Some(tile)
+    }
+
+    /// Puts `tile` on a loaded tile that isn't solid. Returns false if that wasn't possible.
+    pub fn place(&mut self, pos: TilePos, tile: TileType) -> bool {
+        if !self.map.is_loaded(pos) || self.map.is_solid(pos) {
+            return false;
+        }
+        self.set_tile(pos, tile);
+        true
+    }
+
+    /// Takes up to `amount` from the deposit on a loaded tile and returns what was taken, or
+    /// `None` if there's nothing to harvest.
+    pub fn harvest(&mut self, pos: TilePos, amount: u32) -> Option<Deposit> {
+        let (taken, left) = self.map.harvest(pos, amount)?;
+        self.edits.record_harvest(pos, left);
+        self.harvests.send(DepositHarvested {
+            pos,
+            taken: taken.clone(),
+            left,
+        });
+        Some(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{NoiseGenerator, TerrainConfig, TerrainGenerator};
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<TerrainMap>();
+        world.init_resource::<TerrainEdits>();
+        world.init_resource::<Events<TileChanged>>();
+        world.init_resource::<Events<DepositHarvested>>();
+        world
+    }
+
+    /// Generates `coord` the way chunks are streamed in, with the edits put back on top.
+    fn load(world: &mut World, generator: &NoiseGenerator, coord: ChunkCoord) {
+        let mut data = generator.generate_chunk(coord);
+        world.resource::<TerrainEdits>().apply(&mut data);
+        world.resource_mut::<TerrainMap>().insert_chunk(data);
+    }
+
+    #[test]
+    fn edits_survive_unloading() {
+        let generator = NoiseGenerator::new(1, &TerrainConfig::default());
+        let mut world = world();
+        let coord = ChunkCoord::new(-1, 2);
+        let (loaded, unloaded) = (coord.tile(IVec2::new(3, 4)), TilePos::new(500, 500));
+        load(&mut world, &generator, coord);
+
+        world
+            .run_system_once(move |mut editor: TerrainEditor| {
+                editor.set_tile(loaded, TileType::Snow);
+                editor.set_tile(unloaded, TileType::Rock);
+            })
+            .unwrap();
+        assert_eq!(world.resource::<Events<TileChanged>>().len(), 1);
+
+        world.resource_mut::<TerrainMap>().remove_chunk(coord);
+        assert_eq!(world.resource::<TerrainMap>().tile_type(loaded), None);
+        load(&mut world, &generator, coord);
+        load(&mut world, &generator, unloaded.chunk());
+
+        let map = world.resource::<TerrainMap>();
+        assert_eq!(map.tile_type(loaded), Some(TileType::Snow));
+        assert_eq!(map.tile_type(unloaded), Some(TileType::Rock));
+    }
+
+    #[test]
+    fn harvests_survive_unloading() {
+        let generator = NoiseGenerator::new(1, &TerrainConfig::default());
+        let (coord, pos, deposit) = (0..40)
+            .flat_map(|x| (0..40).map(move |y| ChunkCoord::new(x, y)))
+            .find_map(|coord| {
+                let data = generator.generate_chunk(coord);
+                let (local, deposit) = data.deposits().find(|(_, d)| d.amount > 1)?;
+                Some((coord, coord.tile(local), deposit.clone()))
+            })
+            .unwrap();
+        let mut world = world();
+        load(&mut world, &generator, coord);
+
+        let taken = world
+            .run_system_once(move |mut editor: TerrainEditor| editor.harvest(pos, 1))
+            .unwrap();
+        assert_eq!(taken.map(|d| d.amount), Some(1));
+
+        world.resource_mut::<TerrainMap>().remove_chunk(coord);
+        load(&mut world, &generator, coord);
+        let left = world.resource::<TerrainMap>().deposit(pos).cloned();
+        assert_eq!(left.map(|d| d.amount), Some(deposit.amount - 1));
+
+        // editing the tile takes the rest with it
+        world
+            .run_system_once(move |mut editor: TerrainEditor| editor.set_tile(pos, TileType::Sand))
+            .unwrap();
+        world.resource_mut::<TerrainMap>().remove_chunk(coord);
+        load(&mut world, &generator, coord);
+        assert_eq!(world.resource::<TerrainMap>().deposit(pos), None);
+    }
+}
@@ -0,0 +1,195 @@
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{hash_unit, ChunkCoord, TilePos, CHUNK_X, CHUNK_Y};
+
+/// Settings for hydraulic erosion, see [`TerrainConfig::erosion`](crate::TerrainConfig::erosion).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErosionConfig {
+    /// Chance of a droplet starting on any tile.
+    pub droplets: f32,
+    /// Steps a droplet runs for. Erosion reaches twice this far across chunk borders, so
+    /// longer lives get expensive quickly.
+    pub lifetime: u32,
+    /// How much of its direction a droplet keeps each step instead of following the slope.
+    pub inertia: f32,
+    /// Sediment a droplet can carry, relative to its speed, water and the slope.
+    pub capacity: f32,
+    /// Fraction of the free capacity picked up per step.
+    pub erode: f32,
+    /// Fraction of the sediment above capacity dropped per step.
+    pub deposit: f32,
+    /// Fraction of water lost per step.
+    pub evaporate: f32,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> Self {
+        Self {
+            droplets: 0.2,
+            lifetime: 24,
+            inertia: 0.3,
+            capacity: 1.,
+            erode: 0.1,
+            deposit: 0.3,
+            evaporate: 0.02,
+        }
+    }
+}
+
+const GRAVITY: f32 = 10.;
+/// Keeps droplets on flat ground carrying something.
+const MIN_SLOPE: f32 = 0.01;
+
+/// Heights of `coord` after running droplets over `height`, in the order of
+/// [`ChunkCoord::tiles`].
+///
+/// Every droplet runs over the heights as generated instead of what earlier droplets left
+/// behind. That is less dramatic than full erosion, but the result only depends on the droplets
+/// near a tile and not on which chunk asked, so chunk edges still line up.
+pub(crate) fn erode(
+    seed: u32,
+    config: &ErosionConfig,
+    coord: ChunkCoord,
+    height: impl Fn(TilePos) -> f32,
+) -> Vec<f32> {
+    let chunk = IVec2::new(CHUNK_X, CHUNK_Y);
+    let origin = coord.origin().as_ivec2();
+    // droplets move a tile per step, plus one for the jitter of the start and one for touching
+    // the tiles around them
+    let reach = config.lifetime as i32 + 2;
+    let min = origin - 2 * reach;
+    let size = chunk + 4 * reach;
+    let index = |pos: IVec2| ((pos.y - min.y) * size.x + pos.x - min.x) as usize;
+    let heights = (0..size.y)
+        .flat_map(|y| (0..size.x).map(move |x| min + IVec2::new(x, y)))
+        .map(|pos| height(TilePos::from(pos)))
+        .collect::<Vec<_>>();
+    let mut delta = vec![0.; heights.len()];
+
+    // height and gradient between tiles
+    let sample = |pos: Vec2| {
+        let cell = pos.floor().as_ivec2();
+        let f = pos - pos.floor();
+        let h00 = heights[index(cell)];
+        let h10 = heights[index(cell + IVec2::X)];
+        let h01 = heights[index(cell + IVec2::Y)];
+        let h11 = heights[index(cell + IVec2::ONE)];
+        let gradient = Vec2::new(
+            (h10 - h00) * (1. - f.y) + (h11 - h01) * f.y,
+            (h01 - h00) * (1. - f.x) + (h11 - h10) * f.x,
+        );
+        let height =
+            (h00 * (1. - f.x) + h10 * f.x) * (1. - f.y) + (h01 * (1. - f.x) + h11 * f.x) * f.y;
+        (height, gradient)
+    };
+    let mut spread = |pos: Vec2, amount: f32| {
+        let cell = pos.floor().as_ivec2();
+        let f = pos - pos.floor();
+        delta[index(cell)] += amount * (1. - f.x) * (1. - f.y);
+        delta[index(cell + IVec2::X)] += amount * f.x * (1. - f.y);
+        delta[index(cell + IVec2::Y)] += amount * (1. - f.x) * f.y;
+        delta[index(cell + IVec2::ONE)] += amount * f.x * f.y;
+    };
+
+    for y in origin.y - reach..origin.y + CHUNK_Y + reach {
+        for x in origin.x - reach..origin.x + CHUNK_X + reach {
+            if hash_unit(seed, x, y) >= config.droplets {
+                continue;
+            }
+            let jitter = Vec2::new(
+                hash_unit(seed ^ 0x7feb_352d, x, y),
+                hash_unit(seed ^ 0x846c_a68b, x, y),
+            );
+            let mut pos = Vec2::new(x as f32, y as f32) + jitter;
+            let (mut dir, mut speed, mut water, mut sediment) = (Vec2::ZERO, 1., 1., 0.);
+            for _ in 0..config.lifetime {
+                let (height, gradient) = sample(pos);
+                dir = (dir * config.inertia - gradient * (1. - config.inertia)).normalize_or_zero();
+                if dir == Vec2::ZERO {
+                    break;
+                }
+                let next = pos + dir;
+                let dh = sample(next).0 - height;
+                let capacity = (-dh).max(MIN_SLOPE) * speed * water * config.capacity;
+                if dh > 0. || sediment > capacity {
+                    let amount = if dh > 0. {
+                        dh.min(sediment)
+                    } else {
+                        (sediment - capacity) * config.deposit
+                    };
+                    sediment -= amount;
+                    spread(pos, amount);
+                } else {
+                    let amount = ((capacity - sediment) * config.erode).min(-dh);
+                    sediment += amount;
+                    spread(pos, -amount);
+                }
+                speed = (speed * speed - dh * GRAVITY).max(0.).sqrt();
+                water *= 1. - config.evaporate;
+                pos = next;
+            }
+        }
+    }
+
+    coord
+        .tiles()
+        .map(|tile| {
+            let i = index(tile.as_ivec2());
+            heights[i] + delta[i]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NoiseGenerator, TerrainConfig, TerrainGenerator};
+
+    #[test]
+    fn chunks_erode_the_same_in_any_order() {
+        let config = TerrainConfig {
+            erosion: Some(ErosionConfig::default()),
+            ..TerrainConfig::default()
+        };
+        let (a, b) = (
+            NoiseGenerator::new(4, &config),
+            NoiseGenerator::new(4, &config),
+        );
+        let heights = |generator: &NoiseGenerator, coord| {
+            let data = generator.generate_chunk(coord);
+            coord
+                .tiles()
+                .map(|tile| data.height(tile.local()))
+                .collect::<Vec<_>>()
+        };
+        let coords = [(0, 0), (-1, 0), (0, -1), (3, 2)].map(|(x, y)| ChunkCoord::new(x, y));
+        let first = coords.map(|coord| heights(&a, coord));
+        let second = coords
+            .into_iter()
+            .rev()
+            .map(|coord| heights(&b, coord))
+            .collect::<Vec<_>>();
+        assert!(first.iter().eq(second.iter().rev()));
+    }
+
+    #[test]
+    fn erosion_changes_the_heights() {
+        let config = ErosionConfig::default();
+        let height = |tile: TilePos| (tile.x as f32 * 0.3).sin() * 0.2 + tile.y as f32 * 0.01;
+        let coord = ChunkCoord::new(1, -2);
+        let eroded = erode(4, &config, coord, height);
+        assert_eq!(eroded, erode(4, &config, coord, height));
+        let moved = coord
+            .tiles()
+            .zip(&eroded)
+            .filter(|(tile, eroded)| (height(*tile) - **eroded).abs() > 1e-4)
+            .count();
+        assert!(moved > 0);
+        assert!(coord
+            .tiles()
+            .zip(&eroded)
+            .all(|(tile, eroded)| (height(tile) - eroded).abs() < 0.2));
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use bevy::{
+    image::Image,
+    math::IVec2,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{ChunkCoord, TerrainGenerator, TilePos, TileType};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Pictures of a generated region with one pixel per tile, for looking at seeds and config
+/// changes without running the game. Up in the world is up in the images.
+pub struct MapExport {
+    /// Grayscale, black at height 0 and white at 1.
+    pub heights: Image,
+    /// A flat colour per tile type.
+    pub tiles: Image,
+}
+
+impl MapExport {
+    /// Generates every chunk touching the tiles between `min` and `max`, both inclusive.
+    pub fn render(generator: &dyn TerrainGenerator, min: TilePos, max: TilePos) -> Self {
+        let size = (max.as_ivec2() - min.as_ivec2() + 1).max(IVec2::ZERO);
+        let mut heights = vec![0; (size.x * size.y * 4) as usize];
+        let mut tiles = heights.clone();
+
+        let (first, last) = (min.chunk(), max.chunk());
+        for y in first.y..=last.y {
+            for x in first.x..=last.x {
+                let data = generator.generate_chunk(ChunkCoord::new(x, y));
+                for (local, tile) in data.tiles() {
+                    let pos = data.coord.tile(local).as_ivec2() - min.as_ivec2();
+                    if pos.cmplt(IVec2::ZERO).any() || pos.cmpge(size).any() {
+                        continue;
+                    }
+                    let i = (((size.y - 1 - pos.y) * size.x + pos.x) * 4) as usize;
+                    let height = (data.height(local).clamp(0., 1.) * 255.) as u8;
+                    heights[i..i + 4].copy_from_slice(&[height, height, height, 255]);
+                    let [r, g, b] = color(tile);
+                    tiles[i..i + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+
+        let image = |data| {
+            Image::new(
+                Extent3d {
+                    width: size.x as u32,
+                    height: size.y as u32,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+            )
+        };
+        Self {
+            heights: image(heights),
+            tiles: image(tiles),
+        }
+    }
+
+    pub fn save(&self, heights: &Path, tiles: &Path) -> Result<(), Error> {
+        self.heights.clone().try_into_dynamic()?.save(heights)?;
+        self.tiles.clone().try_into_dynamic()?.save(tiles)?;
+        Ok(())
+    }
+}
+
+fn color(tile: TileType) -> [u8; 3] {
+    match tile {
+        TileType::DeepWater => [24, 60, 140],
+        TileType::ShallowWater => [60, 120, 200],
+        TileType::Sand => [220, 200, 130],
+        TileType::Grass => [90, 170, 70],
+        TileType::Forest => [30, 110, 40],
+        TileType::Rock => [120, 120, 120],
+        TileType::Snow => [240, 240, 250],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biome, ChunkData};
+
+    const TILES: [TileType; 7] = [
+        TileType::DeepWater,
+        TileType::ShallowWater,
+        TileType::Sand,
+        TileType::Grass,
+        TileType::Forest,
+        TileType::Rock,
+        TileType::Snow,
+    ];
+
+    /// A column per tile type starting at `x = -3`, getting higher towards the top.
+    struct Columns;
+
+    impl TerrainGenerator for Columns {
+        fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+            ChunkData::from_fn(coord, |pos| {
+                let tile = TILES[(pos.x + 3).rem_euclid(TILES.len() as i32) as usize];
+                (tile, pos.y as f32 / 10., Biome::Grassland)
+            })
+        }
+    }
+
+    #[test]
+    fn one_pixel_per_tile() {
+        let export = MapExport::render(&Columns, TilePos::new(-3, -2), TilePos::new(3, 9));
+        for image in [&export.heights, &export.tiles] {
+            assert_eq!((image.width(), image.height()), (7, 12));
+        }
+        let pixel = |image: &Image, x: u32, y: u32| {
+            let i = ((y * image.width() + x) * 4) as usize;
+            image.data[i..i + 4].to_vec()
+        };
+
+        for (x, tile) in TILES.into_iter().enumerate() {
+            let [r, g, b] = color(tile);
+            // the bottom row is at `y = -2`
+            assert_eq!(pixel(&export.tiles, x as u32, 11), [r, g, b, 255]);
+        }
+        // heights below 0 are black, the top row at `y = 9` is 0.9
+        assert_eq!(pixel(&export.heights, 0, 11), [0, 0, 0, 255]);
+        assert_eq!(pixel(&export.heights, 0, 0), [229, 229, 229, 255]);
+    }
+}
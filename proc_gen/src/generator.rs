@@ -0,0 +1,363 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use noise::NoiseFn;
+use rand::Rng;
+
+use crate::{
+    chunk_rng, deposits::Veins, erosion::erode, hash, hash_unit, mask::BuiltMask, rivers::Rivers,
+    scatter::scatter, structures::placements, Biome, ChunkCoord, Deposit, NoiseGraph, Structure,
+    TerrainConfig, TilePos, TileType, BLOCK_SIZE, CHUNK_X, CHUNK_Y,
+};
+
+/// Produces the tiles of a chunk. Called from background tasks, so it must only depend on its
+/// own state and `coord` for chunks to come out the same regardless of load order.
+pub trait TerrainGenerator: Send + Sync + 'static {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData;
+}
+
+impl<G: TerrainGenerator + ?Sized> TerrainGenerator for Box<G> {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+        (**self).generate_chunk(coord)
+    }
+}
+
+/// Heights and tile types for one chunk, stored row by row starting from the bottom left tile.
+#[derive(Clone)]
+pub struct ChunkData {
+    pub coord: ChunkCoord,
+    heights: Vec<f32>,
+    biomes: Vec<Biome>,
+    tiles: Vec<TileType>,
+    reachable: Vec<bool>,
+    decorations: Vec<Decoration>,
+    deposits: HashMap<IVec2, Deposit>,
+}
+
+/// A prop placed by one of the biome [`DecorationRule`](crate::DecorationRule)s. `pos` is in
+/// world space and `variant` is a random value in `0..1` prefabs can use to vary their looks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    pub pos: Vec2,
+    pub tile: TilePos,
+    pub prop: String,
+    pub variant: f32,
+}
+
+impl ChunkData {
+    /// Builds a chunk from `f`, which is given world tile positions and returns the tile,
+    /// its height and its biome.
+    pub fn from_fn(
+        coord: ChunkCoord,
+        mut f: impl FnMut(TilePos) -> (TileType, f32, Biome),
+    ) -> Self {
+        let mut heights = Vec::with_capacity((CHUNK_X * CHUNK_Y) as usize);
+        let mut biomes = Vec::with_capacity(heights.capacity());
+        let mut tiles = Vec::with_capacity(heights.capacity());
+
+        for pos in coord.tiles() {
+            let (tile, height, biome) = f(pos);
+            heights.push(height);
+            biomes.push(biome);
+            tiles.push(tile);
+        }
+
+        Self {
+            coord,
+            heights,
+            biomes,
+            tiles,
+            reachable: vec![true; (CHUNK_X * CHUNK_Y) as usize],
+            decorations: Vec::new(),
+            deposits: HashMap::new(),
+        }
+    }
+
+    fn index(local: IVec2) -> usize {
+        (local.y * CHUNK_X + local.x) as usize
+    }
+
+    /// Tiles as positions local to the chunk (see [`TilePos::local`]) along with their type.
+    pub fn tiles(&self) -> impl Iterator<Item = (IVec2, TileType)> + '_ {
+        self.tiles.iter().enumerate().map(|(i, tile)| {
+            let i = i as i32;
+            (IVec2::new(i % CHUNK_X, i / CHUNK_X), *tile)
+        })
+    }
+
+    pub fn tile(&self, local: IVec2) -> TileType {
+        self.tiles[Self::index(local)]
+    }
+
+    pub fn set_tile(&mut self, local: IVec2, tile: TileType) {
+        self.tiles[Self::index(local)] = tile;
+    }
+
+    pub fn height(&self, local: IVec2) -> f32 {
+        self.heights[Self::index(local)]
+    }
+
+    pub fn biome(&self, local: IVec2) -> Biome {
+        self.biomes[Self::index(local)]
+    }
+
+    /// False for tiles a [`Connected`](crate::Connected) generator found no way out of.
+    pub fn is_reachable(&self, local: IVec2) -> bool {
+        self.reachable[Self::index(local)]
+    }
+
+    pub fn mark_unreachable(&mut self, local: IVec2) {
+        self.reachable[Self::index(local)] = false;
+    }
+
+    pub fn decorations(&self) -> &[Decoration] {
+        &self.decorations
+    }
+
+    pub fn add_decoration(&mut self, decoration: Decoration) {
+        self.decorations.push(decoration);
+    }
+
+    pub fn retain_decorations(&mut self, f: impl FnMut(&Decoration) -> bool) {
+        self.decorations.retain(f);
+    }
+
+    pub fn deposit(&self, local: IVec2) -> Option<&Deposit> {
+        self.deposits.get(&local)
+    }
+
+    /// Deposits by position local to the chunk.
+    pub fn deposits(&self) -> impl Iterator<Item = (IVec2, &Deposit)> + '_ {
+        self.deposits
+            .iter()
+            .map(|(local, deposit)| (*local, deposit))
+    }
+
+    pub fn set_deposit(&mut self, local: IVec2, deposit: Option<Deposit>) {
+        match deposit {
+            Some(deposit) => self.deposits.insert(local, deposit),
+            None => self.deposits.remove(&local),
+        };
+    }
+}
+
+/// Fractal height noise run through the biome palettes of a [`TerrainConfig`].
+pub struct NoiseGenerator {
+    seed: u32,
+    height: NoiseGraph,
+    temperature: NoiseGraph,
+    moisture: NoiseGraph,
+    mask: BuiltMask,
+    rivers: Option<Rivers>,
+    veins: Veins,
+    config: TerrainConfig,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u32, config: &TerrainConfig) -> Self {
+        Self {
+            seed,
+            height: config.height.build(seed),
+            temperature: config.temperature.build(seed),
+            moisture: config.moisture.build(seed),
+            mask: config
+                .mask
+                .as_ref()
+                .map_or(BuiltMask::None, |mask| mask.build(seed)),
+            rivers: config
+                .rivers
+                .as_ref()
+                .map(|rivers| Rivers::new(seed ^ RIVER_SEED, rivers)),
+            veins: Veins::new(
+                seed ^ DEPOSIT_SEED,
+                &config.veins,
+                config.biomes.values().flat_map(|info| &info.deposits),
+            ),
+            config: config.clone(),
+        }
+    }
+
+    /// Height in `0..1` at a world tile position, with the mask applied.
+    pub fn height(&self, tile: TilePos) -> f32 {
+        let value = self.height.get([tile.x as f64, tile.y as f64]) as f32;
+        (value + 1.) / 2. * self.mask.get(tile)
+    }
+
+    pub fn biome(&self, tile: TilePos) -> Biome {
+        self.config.table.pick(
+            climate(&self.temperature, tile),
+            climate(&self.moisture, tile),
+            hash_unit(self.seed, tile.x, tile.y),
+        )
+    }
+
+    pub fn tile(&self, tile: TilePos) -> TileType {
+        self.config.tile_for(self.biome(tile), self.height(tile))
+    }
+
+    /// Largest height difference between a tile and the four tiles next to it.
+    pub fn slope(&self, tile: TilePos) -> f32 {
+        let height = self.height(tile);
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(x, y)| (self.height(tile.offset(x, y)) - height).abs())
+            .fold(0., f32::max)
+    }
+
+    /// Whether `structure` can go at `corner`, see [`Structure`].
+    fn fits(&self, structure: &Structure, corner: TilePos) -> bool {
+        let size = structure.size();
+        let middle = corner.offset(size.x / 2, size.y / 2);
+        if !structure.biomes.is_empty() && !structure.biomes.contains(&self.biome(middle)) {
+            return false;
+        }
+        let (mut low, mut high) = (f32::INFINITY, f32::NEG_INFINITY);
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let tile = corner.offset(x, y);
+                let height = self.height(tile);
+                let ground = self.config.tile_for(self.biome(tile), height);
+                if !structure.ground.is_empty() && !structure.ground.contains(&ground) {
+                    return false;
+                }
+                (low, high) = (low.min(height), high.max(height));
+            }
+        }
+        structure
+            .max_height_range
+            .is_none_or(|range| high - low <= range)
+    }
+
+    /// Stamps every structure overlapping the chunk and returns the tiles they changed. Later
+    /// structures in the config win where two overlap.
+    fn build_structures(&self, data: &mut ChunkData) -> HashSet<TilePos> {
+        let mut stamped = HashSet::new();
+        for (i, structure) in self.config.structures.iter().enumerate() {
+            let seed = hash(self.seed ^ STRUCTURE_SEED, i as i32, 0);
+            let corners = placements(seed, structure, data.coord, |corner| {
+                self.fits(structure, corner)
+            });
+            for corner in corners {
+                for (offset, tile) in structure.tiles() {
+                    let pos = TilePos::from(corner.as_ivec2() + offset);
+                    if pos.chunk() == data.coord {
+                        data.set_tile(pos.local(), tile);
+                        stamped.insert(pos);
+                    }
+                }
+            }
+        }
+        stamped
+    }
+
+    /// Scatters every decoration rule over the tiles it applies to. A tile keeps the first
+    /// decoration that lands on it.
+    fn decorate(&self, data: &mut ChunkData) {
+        let mut rng = chunk_rng(self.seed, data.coord, DECORATION_STREAM);
+        let mut taken = HashSet::new();
+        // map order changes between runs, the rules must not
+        let mut biomes = self.config.biomes.iter().collect::<Vec<_>>();
+        biomes.sort_by_key(|(biome, _)| **biome as i32);
+
+        for (biome, info) in biomes {
+            for (i, rule) in info.decorations.iter().enumerate() {
+                let seed = hash(self.seed, *biome as i32, i as i32);
+                let points = scatter(seed, data.coord, rule.spacing, |point| {
+                    let tile = TilePos::from(point.floor().as_ivec2());
+                    self.biome(tile) == *biome
+                        && rule.tiles.contains(&self.tile(tile))
+                        && rule.max_slope.is_none_or(|max| self.slope(tile) <= max)
+                });
+                for point in points {
+                    let tile = TilePos::from(point.floor().as_ivec2());
+                    if taken.insert(tile) {
+                        data.add_decoration(Decoration {
+                            pos: point * BLOCK_SIZE,
+                            tile,
+                            prop: rule.prop.clone(),
+                            variant: rng.gen(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Deposits go by the finished tiles, so structures and rivers get them too.
+    fn place_deposits(&self, data: &mut ChunkData) {
+        let deposits = data
+            .coord
+            .tiles()
+            .filter_map(|pos| {
+                let local = pos.local();
+                let rules = &self.config.biomes.get(&data.biome(local))?.deposits;
+                let deposit =
+                    self.veins
+                        .deposit(rules, pos, data.tile(local), data.height(local))?;
+                Some((local, deposit))
+            })
+            .collect::<Vec<_>>();
+        for (local, deposit) in deposits {
+            data.set_deposit(local, Some(deposit));
+        }
+    }
+}
+
+impl TerrainGenerator for NoiseGenerator {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+        let eroded = self.config.erosion.as_ref().map(|erosion| {
+            erode(self.seed ^ EROSION_SEED, erosion, coord, |tile| {
+                self.height(tile)
+            })
+        });
+        let mut data = ChunkData::from_fn(coord, |tile| {
+            let local = tile.local();
+            let height = eroded.as_ref().map_or_else(
+                || self.height(tile),
+                |eroded| eroded[(local.y * CHUNK_X + local.x) as usize],
+            );
+            let biome = self.biome(tile);
+            (self.config.tile_for(biome, height), height, biome)
+        });
+        let mut replaced = self.build_structures(&mut data);
+        if let Some(rivers) = &self.rivers {
+            for pos in rivers.tiles(self, coord) {
+                let local = pos.local();
+                if !replaced.contains(&pos) && !is_water(data.tile(local)) {
+                    data.set_tile(local, rivers.tile());
+                    replaced.insert(pos);
+                }
+            }
+        }
+        self.decorate(&mut data);
+        if eroded.is_some() {
+            // decorations go by the tiles before erosion, drop those whose tile changed since
+            let moved = data
+                .decorations()
+                .iter()
+                .map(|decoration| decoration.tile)
+                .filter(|tile| data.tile(tile.local()) != self.tile(*tile))
+                .collect::<Vec<_>>();
+            replaced.extend(moved);
+        }
+        data.retain_decorations(|decoration| !replaced.contains(&decoration.tile));
+        self.place_deposits(&mut data);
+        data
+    }
+}
+
+const DECORATION_STREAM: u32 = 1;
+const STRUCTURE_SEED: u32 = 0x5bd1_e995;
+const RIVER_SEED: u32 = 0x1b87_3593;
+const EROSION_SEED: u32 = 0xcc9e_2d51;
+const DEPOSIT_SEED: u32 = 0xe654_6b64;
+
+pub(crate) fn is_water(tile: TileType) -> bool {
+    matches!(tile, TileType::DeepWater | TileType::ShallowWater)
+}
+
+/// Perlin output rarely gets near ±1, stretch it a little before mapping it to `0..1`.
+fn climate(noise: &NoiseGraph, tile: TilePos) -> f32 {
+    ((noise.get([tile.x as f64, tile.y as f64]) as f32 * 1.5 + 1.) / 2.).clamp(0., 1.)
+}
@@ -1,86 +1,281 @@
-use std::ops::Range;
+use std::sync::Arc;
 
-use bevy::prelude::*;
-use noise::{NoiseFn, Perlin};
-use rand::Rng;
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-pub struct Terrain;
+pub use autotile::{Autotile, TilesetLayout};
+pub use biome::{Biome, BiomeInfo, BiomeTable, DecorationRule};
+pub use caves::{CaveConfig, CaveGenerator, Pockets};
+pub use chunks::{Chunk, ChunkLoader, ChunkSettings, LoadedChunks, PendingChunks};
+pub use collision::{SweepHit, TerrainColliders};
+pub use config::{TerrainConfig, TerrainConfigHandle, TerrainConfigLoader};
+pub use connectivity::{Connected, ConnectivityConfig, Unreachable};
+pub use coords::{chunk_of, tile_at, ChunkCoord, TilePos};
+pub use deposits::{Deposit, DepositRule};
+pub use edits::{DepositHarvested, TerrainEditor, TerrainEdits, TileChanged};
+pub use erosion::ErosionConfig;
+pub use export::MapExport;
+pub use generator::{ChunkData, Decoration, NoiseGenerator, TerrainGenerator};
+pub use map::{ChunkChanged, RayHit, TerrainMap};
+pub use mask::{Mask, MaskImage};
+pub use nav::{FlowField, NavGrid};
+pub use noise_graph::{Fractal, NoiseGraph, NoiseNode};
+pub use props::{Prop, PropPrefabs};
+pub use render::TileAtlas;
+pub use rivers::RiverConfig;
+pub use save::{LoadWorld, SaveWorld, WorldSave, SAVE_VERSION};
+pub use structures::Structure;
+pub use tiles::{HeightBand, TileInfo, TileType};
+pub use wfc::{WfcConfig, WfcGenerator, WfcRules, WfcSource};
+
+mod autotile;
+mod biome;
+mod caves;
+mod chunks;
+mod collision;
+mod config;
+mod connectivity;
+mod coords;
+mod deposits;
+mod edits;
+mod erosion;
+mod export;
+mod generator;
+mod map;
+mod mask;
+mod nav;
+mod noise_graph;
+mod props;
+mod regions;
+mod render;
+mod rivers;
+mod save;
+mod scatter;
+mod structures;
+mod tiles;
+mod wfc;
+
+/// Streams, generates and renders terrain around [`ChunkLoader`]s. The generator is built from
+/// the world seed and [`TerrainConfig`], and rebuilt whenever either changes.
+pub struct Terrain {
+    factory: GeneratorFactory,
+    props: PropPrefabs,
+}
+
+impl Terrain {
+    pub fn new<G: TerrainGenerator>(
+        factory: impl Fn(u32, &TerrainConfig) -> G + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            factory: GeneratorFactory(Arc::new(move |seed, config| {
+                Arc::new(factory(seed, config))
+            })),
+            props: PropPrefabs::default(),
+        }
+    }
+
+    /// Spawns `prefab` for every decoration of `prop`, see [`PropPrefabs::insert`].
+    pub fn with_prop<B: Bundle>(
+        mut self,
+        prop: impl Into<String>,
+        prefab: impl Fn(&Decoration, &AssetServer) -> B + Send + Sync + 'static,
+    ) -> Self {
+        self.props.insert(prop, prefab);
+        self
+    }
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::new(NoiseGenerator::new)
+    }
+}
 
 pub const CHUNK_X: i32 = 10;
 pub const CHUNK_Y: i32 = 10;
-pub const THRESHOLD: f32 = 0.5;
-
-const NOISE_ZOOM: f64 = 1. / 10.;
-
-const BLOCK_SIZE: f32 = 16.;
-
-fn cartesian_product<A, B>(
-    a: A,
-    b: B,
-) -> impl Iterator<Item = impl Iterator<Item = (A::Item, B::Item)>>
-where
-    A: Iterator,
-    A::Item: Copy,
-    B: Iterator + Clone,
-{
-    a.into_iter().map(move |x| b.clone().map(move |y| (x, y)))
+
+/// Size of a tile in world units.
+pub const BLOCK_SIZE: f32 = 16.;
+
+#[derive(Resource, Clone, Copy)]
+pub struct WorldSeed(pub u32);
+
+impl WorldSeed {
+    pub fn chunk_rng(self, coord: ChunkCoord, stream: u32) -> StdRng {
+        chunk_rng(self.0, coord, stream)
+    }
 }
 
-fn make_map(mut commands: Commands, assets: Res<AssetServer>) {
-    let mut rng = rand::thread_rng();
-    let seed = rng.gen();
-    println!("seed: {seed}");
-    let perlin = Perlin::new(seed);
-
-    let noise_map = get_chunks(&perlin);
-    let grass = assets.load("grass.png");
-
-    let bundles = noise_map.filter_map(move |(x, y, z)| {
-        (z > THRESHOLD).then_some((
-            Sprite {
-                image: grass.clone(),
-                anchor: bevy::sprite::Anchor::BottomLeft,
-                ..Default::default()
-            },
-            Transform::from_xyz(x * BLOCK_SIZE, y * BLOCK_SIZE, 0.),
-        ))
-    });
-
-    commands.spawn_batch(bundles.collect::<Vec<_>>());
+/// RNG for anything randomly placed in `coord`, the same no matter which order chunks are
+/// generated in. Unrelated uses in one chunk should pick different `stream`s so adding draws to
+/// one doesn't shift the other.
+pub fn chunk_rng(seed: u32, coord: ChunkCoord, stream: u32) -> StdRng {
+    let high = hash(seed, coord.x, coord.y) as u64;
+    let low = hash(seed ^ stream.wrapping_mul(0x9e37_79b9), coord.y, coord.x) as u64;
+    StdRng::seed_from_u64(high << 32 | low)
 }
 
-fn get_chunks(perlin: &'_ Perlin) -> impl Iterator<Item = (f32, f32, f32)> + '_ {
-    cartesian_product(-10..10, -10..10)
-        .flatten()
-        .flat_map(move |(chunk_x, chunk_y)| get_chunk(perlin, chunk_x, chunk_y))
+/// The generator every chunk is currently produced by.
+#[derive(Resource, Clone)]
+pub struct Generator(pub Arc<dyn TerrainGenerator>);
+
+type BuildGenerator = dyn Fn(u32, &TerrainConfig) -> Arc<dyn TerrainGenerator> + Send + Sync;
+
+#[derive(Resource, Clone)]
+struct GeneratorFactory(Arc<BuildGenerator>);
+
+impl GeneratorFactory {
+    fn build(&self, seed: u32, config: &TerrainConfig) -> Generator {
+        Generator((self.0)(seed, config))
+    }
+}
+
+/// Cheap integer hash of a tile position, stable for a given seed.
+pub(crate) fn hash(seed: u32, x: i32, y: i32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (y as u32).wrapping_mul(0x1656_67b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// [`hash`] mapped to `0..1`.
+pub(crate) fn hash_unit(seed: u32, x: i32, y: i32) -> f32 {
+    hash(seed, x, y) as f32 / u32::MAX as f32
 }
 
-fn get_chunk(
-    perlin: &'_ Perlin,
-    chunk_x: i32,
-    chunk_y: i32,
-) -> impl Iterator<Item = (f32, f32, f32)> + '_ {
-    let (x_range, y_range) = get_chunk_extents(chunk_x, chunk_y);
-    cartesian_product(x_range, y_range).flatten().map(|(i, j)| {
-        (
-            i as f32,
-            j as f32,
-            perlin
-                .get([(i as f64) * NOISE_ZOOM, (j as f64) * NOISE_ZOOM])
-                .abs() as f32,
-        )
-    })
+#[derive(Resource)]
+struct TerrainAssets {
+    tiles: HashMap<TileType, Handle<Image>>,
 }
 
-fn get_chunk_extents(chunk_pos_x: i32, chunk_pos_y: i32) -> (Range<i32>, Range<i32>) {
-    (
-        (chunk_pos_x * CHUNK_X)..((chunk_pos_x * CHUNK_X) + (CHUNK_X)),
-        (chunk_pos_y * CHUNK_Y)..((chunk_pos_y * CHUNK_Y) + (CHUNK_Y)),
-    )
+impl TerrainAssets {
+    fn load(assets: &AssetServer, config: &TerrainConfig) -> Self {
+        Self {
+            tiles: config
+                .tiles
+                .iter()
+                .map(|(tile, info)| {
+                    let path = info
+                        .autotile
+                        .as_ref()
+                        .map_or(&info.texture, |autotile| &autotile.tileset);
+                    (*tile, assets.load(path))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Sent when the loaded chunks were thrown away for a new seed or config, anything placed on
+/// the old terrain may need to be moved.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Regenerated;
+
+/// Everything needed to throw away the loaded chunks and start generating from a new seed or
+/// config.
+#[derive(SystemParam)]
+pub(crate) struct Regenerate<'w, 's> {
+    commands: Commands<'w, 's>,
+    events: EventWriter<'w, Regenerated>,
+    factory: Res<'w, GeneratorFactory>,
+    assets: Res<'w, AssetServer>,
+    loaded: ResMut<'w, LoadedChunks>,
+    pending: ResMut<'w, PendingChunks>,
+    map: ResMut<'w, TerrainMap>,
+}
+
+impl Regenerate<'_, '_> {
+    pub(crate) fn run(&mut self, seed: u32, config: &TerrainConfig) {
+        self.commands.insert_resource(WorldSeed(seed));
+        self.commands
+            .insert_resource(self.factory.build(seed, config));
+        self.commands
+            .insert_resource(TerrainAssets::load(&self.assets, config));
+        self.commands.remove_resource::<TileAtlas>();
+        self.commands.insert_resource(config.clone());
+        self.loaded.clear(&mut self.commands);
+        self.pending.clear();
+        self.map.reset(config.tiles.clone());
+        self.events.send(Regenerated);
+    }
+}
+
+/// Uses the [`WorldSeed`] if one was inserted before startup, a random one otherwise.
+fn setup(seed: Option<Res<WorldSeed>>, config: Res<TerrainConfig>, mut regenerate: Regenerate) {
+    let seed = seed.map_or_else(|| rand::thread_rng().gen(), |seed| seed.0);
+    info!("seed: {seed}");
+    regenerate.run(seed, &config);
+}
+
+/// Swaps in the config from [`TerrainConfigHandle`] once it loads and regenerates the world with it.
+fn apply_config(
+    mut events: EventReader<AssetEvent<TerrainConfig>>,
+    handle: Option<Res<TerrainConfigHandle>>,
+    configs: Res<Assets<TerrainConfig>>,
+    seed: Res<WorldSeed>,
+    mut regenerate: Regenerate,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    let changed = events
+        .read()
+        .filter(|event| {
+            event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0)
+        })
+        .count()
+        > 0;
+    let Some(config) = configs.get(&handle.0).filter(|_| changed) else {
+        return;
+    };
+
+    regenerate.run(seed.0, config);
 }
 
 impl Plugin for Terrain {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, make_map);
+        app.insert_resource(self.factory.clone())
+            .insert_resource(self.props.clone())
+            .init_resource::<ChunkSettings>()
+            .init_resource::<TerrainConfig>()
+            .init_asset::<TerrainConfig>()
+            .init_asset_loader::<TerrainConfigLoader>()
+            .init_resource::<LoadedChunks>()
+            .init_resource::<PendingChunks>()
+            .init_resource::<TerrainMap>()
+            .init_resource::<TerrainColliders>()
+            .init_resource::<NavGrid>()
+            .init_resource::<TerrainEdits>()
+            .add_event::<ChunkChanged>()
+            .add_event::<TileChanged>()
+            .add_event::<DepositHarvested>()
+            .add_event::<SaveWorld>()
+            .add_event::<LoadWorld>()
+            .add_event::<Regenerated>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    (
+                        apply_config,
+                        save::save_world,
+                        save::load_world,
+                        chunks::stream_chunks,
+                        chunks::spawn_ready_chunks,
+                        map::send_chunk_changes,
+                        (
+                            render::mesh_chunks,
+                            collision::update_colliders,
+                            nav::update_nav_grid,
+                            props::spawn_props,
+                            props::remove_edited_props,
+                        ),
+                    )
+                        .chain(),
+                    render::build_atlas.run_if(not(resource_exists::<TileAtlas>)),
+                ),
+            );
     }
 }
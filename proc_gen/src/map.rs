@@ -0,0 +1,306 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{ChunkCoord, ChunkData, Deposit, TileInfo, TilePos, TileType, BLOCK_SIZE};
+
+/// Tile data of every loaded chunk, for gameplay code that needs to ask about the world.
+/// Tiles outside loaded chunks are unknown: they are neither solid nor walkable.
+#[derive(Resource, Default)]
+pub struct TerrainMap {
+    chunks: HashMap<ChunkCoord, ChunkData>,
+    info: HashMap<TileType, TileInfo>,
+    dirty: HashSet<ChunkCoord>,
+}
+
+/// Sent when a chunk was loaded into or unloaded from the [`TerrainMap`]. Single tile edits
+/// send [`TileChanged`](crate::TileChanged) instead.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ChunkChanged(pub ChunkCoord);
+
+/// Where a ray first entered a solid tile. `normal` points out of the face that was hit and is
+/// zero when the ray started inside the tile.
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub tile: TilePos,
+    pub point: Vec2,
+    pub normal: Vec2,
+    pub distance: f32,
+}
+
+impl TerrainMap {
+    pub fn chunk(&self, coord: ChunkCoord) -> Option<&ChunkData> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn is_loaded(&self, pos: TilePos) -> bool {
+        self.chunks.contains_key(&pos.chunk())
+    }
+
+    pub fn tile_type(&self, pos: TilePos) -> Option<TileType> {
+        self.chunk(pos.chunk()).map(|chunk| chunk.tile(pos.local()))
+    }
+
+    pub fn info(&self, pos: TilePos) -> Option<&TileInfo> {
+        self.info.get(&self.tile_type(pos)?)
+    }
+
+    pub fn is_solid(&self, pos: TilePos) -> bool {
+        self.info(pos).is_some_and(|info| info.solid)
+    }
+
+    pub fn is_walkable(&self, pos: TilePos) -> bool {
+        self.info(pos).is_some_and(|info| info.walkable)
+    }
+
+    /// Whether the tile was reachable as generated, false if it isn't loaded.
+    pub fn is_reachable(&self, pos: TilePos) -> bool {
+        self.chunk(pos.chunk())
+            .is_some_and(|chunk| chunk.is_reachable(pos.local()))
+    }
+
+    /// What can be harvested on a loaded tile, see [`DepositRule`](crate::DepositRule).
+    pub fn deposit(&self, pos: TilePos) -> Option<&Deposit> {
+        self.chunk(pos.chunk())?.deposit(pos.local())
+    }
+
+    /// Loaded deposits in the rectangle between `min` and `max`, both inclusive.
+    pub fn deposits(
+        &self,
+        min: TilePos,
+        max: TilePos,
+    ) -> impl Iterator<Item = (TilePos, &Deposit)> {
+        let (first, last) = (min.chunk(), max.chunk());
+        (first.y..=last.y)
+            .flat_map(move |y| (first.x..=last.x).map(move |x| ChunkCoord::new(x, y)))
+            .filter_map(|coord| self.chunk(coord))
+            .flat_map(|chunk| {
+                chunk
+                    .deposits()
+                    .map(|(local, deposit)| (chunk.coord.tile(local), deposit))
+            })
+            .filter(move |(pos, _)| {
+                (min.x..=max.x).contains(&pos.x) && (min.y..=max.y).contains(&pos.y)
+            })
+    }
+
+    /// Loaded tiles in the rectangle between `min` and `max`, both inclusive.
+    pub fn region(
+        &self,
+        min: TilePos,
+        max: TilePos,
+    ) -> impl Iterator<Item = (TilePos, TileType)> + '_ {
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| TilePos::new(x, y)))
+            .filter_map(|pos| Some((pos, self.tile_type(pos)?)))
+    }
+
+    /// Walks the tiles crossed by the segment `from..to` (in world space) and returns the first
+    /// solid one.
+    pub fn raycast(&self, from: Vec2, to: Vec2) -> Option<RayHit> {
+        let start = from / BLOCK_SIZE;
+        let delta = to / BLOCK_SIZE - start;
+        let length = delta.length();
+        let mut tile = TilePos::from(start.floor().as_ivec2());
+
+        if self.is_solid(tile) {
+            return Some(RayHit {
+                tile,
+                point: from,
+                normal: Vec2::ZERO,
+                distance: 0.,
+            });
+        }
+        if length == 0. {
+            return None;
+        }
+
+        let dir = delta / length;
+        let step = IVec2::new(sign(dir.x), sign(dir.y));
+        let t_delta = Vec2::new(1. / dir.x.abs(), 1. / dir.y.abs());
+        let boundary = |pos: f32, cell: i32, step: i32, dir: f32| match step {
+            1 => (cell as f32 + 1. - pos) / dir,
+            -1 => (pos - cell as f32) / -dir,
+            _ => f32::INFINITY,
+        };
+        let mut t_max = Vec2::new(
+            boundary(start.x, tile.x, step.x, dir.x),
+            boundary(start.y, tile.y, step.y, dir.y),
+        );
+
+        loop {
+            let (t, normal) = if t_max.x < t_max.y {
+                tile.x += step.x;
+                t_max.x += t_delta.x;
+                (t_max.x - t_delta.x, Vec2::new(-step.x as f32, 0.))
+            } else {
+                tile.y += step.y;
+                t_max.y += t_delta.y;
+                (t_max.y - t_delta.y, Vec2::new(0., -step.y as f32))
+            };
+            if t > length {
+                return None;
+            }
+            if self.is_solid(tile) {
+                return Some(RayHit {
+                    tile,
+                    point: (start + dir * t) * BLOCK_SIZE,
+                    normal,
+                    distance: t * BLOCK_SIZE,
+                });
+            }
+        }
+    }
+
+    /// Center of the walkable tile closest to `near` (in world space) with nothing solid or
+    /// unloaded within `clearance` of it, looking up to `max_distance` tiles away. Tiles marked
+    /// unreachable are skipped so nobody spawns in a pocket cut off from the rest of the world,
+    /// but only [`Connected`](crate::Connected) marks them. With other generators the spawn may
+    /// well be in such a pocket.
+    pub fn find_spawn(&self, near: Vec2, clearance: f32, max_distance: i32) -> Option<Vec2> {
+        let start = TilePos::from_world(near);
+        let reach = (clearance / BLOCK_SIZE).ceil() as i32;
+        let mut candidates = (-max_distance..=max_distance)
+            .flat_map(|y| (-max_distance..=max_distance).map(move |x| start.offset(x, y)))
+            .map(|pos| (pos.center().distance_squared(near), pos))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let is_clear = |center: Vec2, pos: TilePos| {
+            (-reach..=reach)
+                .flat_map(|y| (-reach..=reach).map(move |x| pos.offset(x, y)))
+                .all(|other| {
+                    let min = other.to_world();
+                    let closest = center.clamp(min, min + BLOCK_SIZE);
+                    closest.distance(center) >= clearance
+                        || (self.is_loaded(other) && !self.is_solid(other))
+                })
+        };
+        candidates
+            .into_iter()
+            .map(|(_, pos)| (pos.center(), pos))
+            .find(|(center, pos)| {
+                self.is_walkable(*pos) && self.is_reachable(*pos) && is_clear(*center, *pos)
+            })
+            .map(|(center, _)| center)
+    }
+
+    /// Changes a loaded tile and returns what was there before. Edits go through
+    /// [`TerrainEditor`](crate::TerrainEditor) so they are recorded and announced.
+    pub(crate) fn set_tile(&mut self, pos: TilePos, tile: TileType) -> Option<TileType> {
+        let chunk = self.chunks.get_mut(&pos.chunk())?;
+        let old = chunk.tile(pos.local());
+        chunk.set_tile(pos.local(), tile);
+        Some(old)
+    }
+
+    /// Takes up to `amount` from a loaded deposit, returning what was taken and how much is left.
+    pub(crate) fn harvest(&mut self, pos: TilePos, amount: u32) -> Option<(Deposit, u32)> {
+        let chunk = self.chunks.get_mut(&pos.chunk())?;
+        let deposit = chunk.deposit(pos.local())?.clone();
+        let taken = amount.min(deposit.amount);
+        let left = deposit.amount - taken;
+        chunk.set_deposit(
+            pos.local(),
+            (left > 0).then(|| Deposit {
+                resource: deposit.resource.clone(),
+                amount: left,
+            }),
+        );
+        let taken = Deposit {
+            resource: deposit.resource,
+            amount: taken,
+        };
+        Some((taken, left))
+    }
+
+    pub(crate) fn clear_deposit(&mut self, pos: TilePos) {
+        if let Some(chunk) = self.chunks.get_mut(&pos.chunk()) {
+            chunk.set_deposit(pos.local(), None);
+        }
+    }
+
+    pub(crate) fn insert_chunk(&mut self, data: ChunkData) {
+        self.dirty.insert(data.coord);
+        self.chunks.insert(data.coord, data);
+    }
+
+    pub(crate) fn remove_chunk(&mut self, coord: ChunkCoord) {
+        if self.chunks.remove(&coord).is_some() {
+            self.dirty.insert(coord);
+        }
+    }
+
+    pub(crate) fn reset(&mut self, info: HashMap<TileType, TileInfo>) {
+        self.dirty
+            .extend(self.chunks.drain().map(|(coord, _)| coord));
+        self.info = info;
+    }
+}
+
+pub fn send_chunk_changes(mut map: ResMut<TerrainMap>, mut events: EventWriter<ChunkChanged>) {
+    if !map.dirty.is_empty() {
+        events.send_batch(map.dirty.drain().map(ChunkChanged));
+    }
+}
+
+fn sign(value: f32) -> i32 {
+    if value > 0. {
+        1
+    } else if value < 0. {
+        -1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biome, TerrainConfig};
+
+    /// Grass in chunks `(0, 0)` and `(1, 0)` with a rock wall at `x = 12`, past the border.
+    fn map() -> TerrainMap {
+        let mut map = TerrainMap::default();
+        map.reset(TerrainConfig::default().tiles);
+        for coord in [ChunkCoord::new(0, 0), ChunkCoord::new(1, 0)] {
+            map.insert_chunk(ChunkData::from_fn(coord, |pos| {
+                let tile = if pos.x == 12 {
+                    TileType::Rock
+                } else {
+                    TileType::Grass
+                };
+                (tile, 0.5, Biome::Grassland)
+            }));
+        }
+        map
+    }
+
+    #[test]
+    fn ray_hits_a_wall_in_the_next_chunk() {
+        let from = TilePos::new(5, 5).center();
+        let hit = map().raycast(from, TilePos::new(15, 5).center()).unwrap();
+        assert_eq!(hit.tile, TilePos::new(12, 5));
+        assert_eq!(hit.normal, Vec2::new(-1., 0.));
+        assert!((hit.point.x - 12. * BLOCK_SIZE).abs() < 1e-3);
+        assert!((hit.distance - (12. * BLOCK_SIZE - from.x)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ray_starting_in_a_wall_hits_right_away() {
+        let from = TilePos::new(12, 3).center();
+        let hit = map().raycast(from, TilePos::new(2, 3).center()).unwrap();
+        assert_eq!(hit.tile, TilePos::new(12, 3));
+        assert_eq!(hit.normal, Vec2::ZERO);
+        assert_eq!(hit.distance, 0.);
+    }
+
+    #[test]
+    fn ray_stops_short_of_the_wall() {
+        let map = map();
+        let from = TilePos::new(5, 5).center();
+        assert!(map.raycast(from, TilePos::new(11, 8).center()).is_none());
+        assert!(map.raycast(from, from).is_none());
+    }
+}
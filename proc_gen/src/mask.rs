@@ -0,0 +1,273 @@
+use std::{path::PathBuf, sync::Arc};
+
+use bevy::{
+    asset::LoadContext,
+    image::{CompressedImageFormats, Image, ImageSampler, ImageType},
+    log::warn,
+    math::{IVec2, Vec2},
+    render::render_asset::RenderAssetUsages,
+};
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+
+use crate::{noise_graph::NoiseNode, NoiseGraph, TilePos};
+
+/// A shape multiplied into the height, `1` keeps the terrain and `0` sinks it to the bottom of
+/// the sea. Gives the world a coastline instead of going on forever.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Mask {
+    /// Full height within `radius * (1 - falloff)` tiles of `center`, fading out to nothing at
+    /// `radius`.
+    Island {
+        center: IVec2,
+        radius: f32,
+        falloff: f32,
+    },
+    /// An island whose radius is bent by `shape` (in `-1..1`) by up to `strength` of itself,
+    /// so the coast gets bays and peninsulas.
+    Continent {
+        center: IVec2,
+        radius: f32,
+        falloff: f32,
+        shape: NoiseNode,
+        strength: f32,
+    },
+    /// Brightness of a grayscale image stretched over `size` tiles starting at `min`. Everything
+    /// outside the image is sea. `path` is in the assets folder and read along with the config,
+    /// see [`Mask::gradient`] for configs made in code. The pixels are kept in `image`, so saved
+    /// worlds don't need the file.
+    Gradient {
+        path: PathBuf,
+        min: IVec2,
+        size: IVec2,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<MaskImage>,
+    },
+}
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Brightness of every pixel from the top left, row by row. The pixels are shared between
+/// clones, configs get cloned for every generator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaskImage {
+    width: u32,
+    height: u32,
+    pixels: Arc<[u8]>,
+}
+
+impl MaskImage {
+    fn from_image(image: &Image) -> Result<Self, Error> {
+        let luma = image.clone().try_into_dynamic()?.into_luma8();
+        let (width, height) = luma.dimensions();
+        Ok(Self {
+            width,
+            height,
+            pixels: luma.into_raw().into(),
+        })
+    }
+
+    /// Whether there are pixels at all and as many as the size says.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "the mask image is {width}x{height}, it can't be empty"
+            ));
+        }
+        if self.pixels.len() != width * height {
+            return Err(format!(
+                "the mask image is {width}x{height} but has {} pixels",
+                self.pixels.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Mask {
+    pub fn island(radius: f32) -> Self {
+        Mask::Island {
+            center: IVec2::ZERO,
+            radius,
+            falloff: 0.5,
+        }
+    }
+
+    /// A [`Mask::Gradient`] of an image that's already loaded.
+    pub fn gradient(image: &Image, min: IVec2, size: IVec2) -> Result<Self, Error> {
+        Ok(Mask::Gradient {
+            path: PathBuf::new(),
+            min,
+            size,
+            image: Some(MaskImage::from_image(image)?),
+        })
+    }
+
+    /// Reads the image of a gradient mask as a dependency of the config being loaded.
+    pub(crate) async fn load(&mut self, load_context: &mut LoadContext<'_>) -> Result<(), Error> {
+        let Mask::Gradient { path, image, .. } = self else {
+            return Ok(());
+        };
+        let bytes = load_context.read_asset_bytes(path.clone()).await?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+        let decoded = Image::from_buffer(
+            &bytes,
+            ImageType::Extension(extension),
+            CompressedImageFormats::NONE,
+            // only sRGB converts back to pixels, the values are read as they are stored either way
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::default(),
+        )?;
+        *image = Some(MaskImage::from_image(&decoded)?);
+        Ok(())
+    }
+
+    pub(crate) fn build(&self, seed: u32) -> BuiltMask {
+        match self {
+            Mask::Island {
+                center,
+                radius,
+                falloff,
+            } => BuiltMask::Radial {
+                center: center.as_vec2(),
+                radius: *radius,
+                falloff: *falloff,
+                shape: None,
+            },
+            Mask::Continent {
+                center,
+                radius,
+                falloff,
+                shape,
+                strength,
+            } => BuiltMask::Radial {
+                center: center.as_vec2(),
+                radius: *radius,
+                falloff: *falloff,
+                shape: Some((shape.build(seed), *strength)),
+            },
+            Mask::Gradient {
+                path,
+                min,
+                size,
+                image,
+            } => match image {
+                Some(image) => BuiltMask::Gradient {
+                    min: *min,
+                    size: *size,
+                    image: image.clone(),
+                },
+                None => {
+                    warn!("mask {} was never loaded, ignoring it", path.display());
+                    BuiltMask::None
+                }
+            },
+        }
+    }
+}
+
+pub(crate) enum BuiltMask {
+    None,
+    Radial {
+        center: Vec2,
+        radius: f32,
+        falloff: f32,
+        shape: Option<(NoiseGraph, f32)>,
+    },
+    Gradient {
+        min: IVec2,
+        size: IVec2,
+        image: MaskImage,
+    },
+}
+
+impl BuiltMask {
+    pub(crate) fn get(&self, tile: TilePos) -> f32 {
+        match self {
+            BuiltMask::None => 1.,
+            BuiltMask::Radial {
+                center,
+                radius,
+                falloff,
+                shape,
+            } => {
+                let pos = tile.as_ivec2().as_vec2();
+                let bend = shape.as_ref().map_or(0., |(shape, strength)| {
+                    shape.get([pos.x as f64, pos.y as f64]) as f32 * strength
+                });
+                let radius = radius * (1. + bend);
+                let inner = radius * (1. - falloff);
+                let t = ((pos.distance(*center) - inner) / (radius - inner).max(f32::EPSILON))
+                    .clamp(0., 1.);
+                1. - t * t * (3. - 2. * t)
+            }
+            BuiltMask::Gradient { min, size, image } => {
+                let local = (tile.as_ivec2() - *min).as_vec2() / size.as_vec2();
+                if local.cmplt(Vec2::ZERO).any() || local.cmpge(Vec2::ONE).any() {
+                    return 0.;
+                }
+                let x = (local.x * image.width as f32) as u32;
+                // images go top down, tiles bottom up
+                let y = ((1. - local.y) * image.height as f32) as u32;
+                image.pixels[(y.min(image.height - 1) * image.width + x) as usize] as f32 / 255.
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TerrainConfig;
+
+    fn gradient(width: u32, height: u32, pixels: usize) -> TerrainConfig {
+        TerrainConfig {
+            mask: Some(Mask::Gradient {
+                path: PathBuf::new(),
+                min: IVec2::ZERO,
+                size: IVec2::splat(8),
+                image: Some(MaskImage {
+                    width,
+                    height,
+                    pixels: vec![255; pixels].into(),
+                }),
+            }),
+            ..TerrainConfig::default()
+        }
+    }
+
+    #[test]
+    fn images_must_match_their_size() {
+        assert!(gradient(2, 3, 6).validate().is_ok());
+        assert!(gradient(2, 3, 5).validate().is_err());
+        assert!(gradient(2, 3, 7).validate().is_err());
+        assert!(gradient(0, 3, 0).validate().is_err());
+        assert!(gradient(2, 0, 0).validate().is_err());
+    }
+
+    #[test]
+    fn clones_share_the_pixels() {
+        let config = gradient(2, 3, 6);
+        let pixels = |config: &TerrainConfig| match &config.mask {
+            Some(Mask::Gradient {
+                image: Some(image), ..
+            }) => image.pixels.clone(),
+            _ => unreachable!(),
+        };
+        assert!(Arc::ptr_eq(&pixels(&config), &pixels(&config.clone())));
+    }
+
+    #[test]
+    fn gradient_covers_its_size() {
+        let Some(mask) = &gradient(2, 3, 6).mask else {
+            unreachable!()
+        };
+        let mask = mask.build(0);
+        assert_eq!(mask.get(TilePos::new(0, 0)), 1.);
+        assert_eq!(mask.get(TilePos::new(7, 7)), 1.);
+        assert_eq!(mask.get(TilePos::new(8, 0)), 0.);
+        assert_eq!(mask.get(TilePos::new(-1, 0)), 0.);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{ChunkChanged, ChunkCoord, TerrainMap, TileChanged, TilePos, CHUNK_X, CHUNK_Y};
+
+/// Costs of a step scaled by 10 so diagonals stay integers.
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+
+const STEPS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Which tiles of every loaded chunk can be walked through, for path finding. Unloaded tiles
+/// are blocked.
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    chunks: HashMap<ChunkCoord, Vec<bool>>,
+}
+
+impl NavGrid {
+    pub fn is_walkable(&self, pos: TilePos) -> bool {
+        let local = pos.local();
+        self.chunks
+            .get(&pos.chunk())
+            .is_some_and(|tiles| tiles[(local.y * CHUNK_X + local.x) as usize])
+    }
+
+    /// Tiles next to `pos` that can be stepped to, with the cost of the step. Diagonal steps
+    /// need both tiles beside them to be free so paths don't cut corners.
+    fn neighbours(&self, pos: TilePos) -> impl Iterator<Item = (TilePos, u32)> + '_ {
+        STEPS.into_iter().filter_map(move |(x, y)| {
+            let next = pos.offset(x, y);
+            if !self.is_walkable(next) {
+                return None;
+            }
+            if x != 0 && y != 0 {
+                (self.is_walkable(pos.offset(x, 0)) && self.is_walkable(pos.offset(0, y)))
+                    .then_some((next, DIAGONAL))
+            } else {
+                Some((next, STRAIGHT))
+            }
+        })
+    }
+
+    /// Shortest path from `from` to `to` with both ends included, found with A*. Gives up after
+    /// expanding `max_nodes` tiles, since a target that can't be reached would otherwise search
+    /// everything that is loaded.
+    pub fn find_path(&self, from: TilePos, to: TilePos, max_nodes: usize) -> Option<Vec<TilePos>> {
+        if !self.is_walkable(from) || !self.is_walkable(to) {
+            return None;
+        }
+        let mut open = BinaryHeap::from([Reverse((estimate(from, to), 0_u32, from))]);
+        let mut came_from = HashMap::from([(from, from)]);
+        let mut costs = HashMap::from([(from, 0)]);
+        let mut closed = HashSet::new();
+
+        while let Some(Reverse((_, cost, pos))) = open.pop() {
+            if pos == to {
+                let mut path = vec![pos];
+                let mut pos = pos;
+                while pos != from {
+                    pos = came_from[&pos];
+                    path.push(pos);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if !closed.insert(pos) {
+                continue;
+            }
+            if closed.len() > max_nodes {
+                return None;
+            }
+            for (next, step) in self.neighbours(pos) {
+                let cost = cost.saturating_add(step);
+                if costs.get(&next).is_none_or(|old| cost < *old) {
+                    costs.insert(next, cost);
+                    came_from.insert(next, pos);
+                    open.push(Reverse((cost + estimate(next, to), cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// The way to `target` from every tile that can reach it within `max_cost` (in tiles), for
+    /// many agents heading the same way.
+    pub fn flow_field(&self, target: TilePos, max_cost: u32) -> FlowField {
+        let mut steps = HashMap::new();
+        let mut open = BinaryHeap::new();
+        if self.is_walkable(target) {
+            open.push(Reverse((0_u32, target, target)));
+        }
+        let max_cost = max_cost.saturating_mul(STRAIGHT);
+        while let Some(Reverse((cost, pos, next))) = open.pop() {
+            if steps.contains_key(&pos) {
+                continue;
+            }
+            steps.insert(pos, (cost, next));
+            for (neighbour, step) in self.neighbours(pos) {
+                let cost = cost.saturating_add(step);
+                if cost <= max_cost && !steps.contains_key(&neighbour) {
+                    open.push(Reverse((cost, neighbour, pos)));
+                }
+            }
+        }
+        FlowField { steps }
+    }
+}
+
+/// Octile distance, exact on an empty grid.
+fn estimate(from: TilePos, to: TilePos) -> u32 {
+    let delta = (to.as_ivec2() - from.as_ivec2()).abs();
+    let (short, long) = (delta.min_element() as u32, delta.max_element() as u32);
+    short * DIAGONAL + (long - short) * STRAIGHT
+}
+
+/// See [`NavGrid::flow_field`]. Doesn't follow later changes to the grid.
+#[derive(Debug, Clone, Default)]
+pub struct FlowField {
+    /// Cost to the target and the tile to go to next.
+    steps: HashMap<TilePos, (u32, TilePos)>,
+}
+
+impl FlowField {
+    /// Cost of getting to the target, scaled so a straight step costs 10.
+    pub fn cost(&self, pos: TilePos) -> Option<u32> {
+        self.steps.get(&pos).map(|(cost, _)| *cost)
+    }
+
+    /// The tile to go to next, the target itself once there. `None` for tiles outside the
+    /// field.
+    pub fn next(&self, pos: TilePos) -> Option<TilePos> {
+        self.steps.get(&pos).map(|(_, next)| *next)
+    }
+
+    /// Unit vector towards [`next`](Self::next), zero on the target.
+    pub fn direction(&self, pos: TilePos) -> Option<Vec2> {
+        let next = self.next(pos)?;
+        Some(
+            (next.as_ivec2() - pos.as_ivec2())
+                .as_vec2()
+                .normalize_or_zero(),
+        )
+    }
+}
+
+pub fn update_nav_grid(
+    mut changes: EventReader<ChunkChanged>,
+    mut edits: EventReader<TileChanged>,
+    map: Res<TerrainMap>,
+    mut grid: ResMut<NavGrid>,
+) {
+    let edited = edits
+        .read()
+        .map(|edit| edit.pos.chunk())
+        .collect::<HashSet<_>>();
+    let changed = changes.read().map(|ChunkChanged(coord)| *coord);
+    for coord in edited.into_iter().chain(changed) {
+        if map.is_loaded(coord.origin()) {
+            let tiles = (0..CHUNK_Y)
+                .flat_map(|y| (0..CHUNK_X).map(move |x| coord.tile(IVec2::new(x, y))))
+                .map(|pos| map.is_walkable(pos) && !map.is_solid(pos))
+                .collect();
+            grid.chunks.insert(coord, tiles);
+        } else {
+            grid.chunks.remove(&coord);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chunks `-2..2` on both axes, walkable apart from `walls`.
+    fn grid(walls: &[TilePos]) -> NavGrid {
+        let mut grid = NavGrid::default();
+        for y in -2..2 {
+            for x in -2..2 {
+                let coord = ChunkCoord::new(x, y);
+                let tiles = coord.tiles().map(|pos| !walls.contains(&pos)).collect();
+                grid.chunks.insert(coord, tiles);
+            }
+        }
+        grid
+    }
+
+    fn path_cost(path: &[TilePos]) -> u32 {
+        path.windows(2)
+            .map(
+                |step| match (step[1].as_ivec2() - step[0].as_ivec2()).abs() {
+                    IVec2 { x: 1, y: 1 } => DIAGONAL,
+                    _ => STRAIGHT,
+                },
+            )
+            .sum()
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let walls = (-5..=5).map(|y| TilePos::new(3, y)).collect::<Vec<_>>();
+        let grid = grid(&walls);
+        let (from, to) = (TilePos::new(0, 0), TilePos::new(6, 0));
+        let path = grid.find_path(from, to, 10_000).unwrap();
+
+        assert_eq!(path.first(), Some(&from));
+        assert_eq!(path.last(), Some(&to));
+        for step in path.windows(2) {
+            let delta = step[1].as_ivec2() - step[0].as_ivec2();
+            assert!(
+                delta.abs().max_element() == 1,
+                "{step:?} isn't a single step"
+            );
+            assert!(grid.is_walkable(step[1]));
+            if delta.x != 0 && delta.y != 0 {
+                assert!(grid.is_walkable(step[0].offset(delta.x, 0)));
+                assert!(grid.is_walkable(step[0].offset(0, delta.y)));
+            }
+        }
+        // up to (2, 6), across the top of the wall and down the other side the same way
+        assert_eq!(
+            path_cost(&path),
+            2 * (2 * DIAGONAL + 4 * STRAIGHT) + 2 * STRAIGHT
+        );
+    }
+
+    #[test]
+    fn no_path_to_an_enclosed_tile() {
+        let target = TilePos::new(5, 5);
+        let walls = STEPS
+            .map(|(x, y)| target.offset(x, y))
+            .into_iter()
+            .collect::<Vec<_>>();
+        let grid = grid(&walls);
+        assert_eq!(grid.find_path(TilePos::new(0, 0), target, 10_000), None);
+        assert_eq!(
+            grid.find_path(TilePos::new(0, 0), TilePos::new(50, 0), 10_000),
+            None
+        );
+    }
+
+    #[test]
+    fn flow_field_agrees_with_find_path() {
+        let walls = (-8..=4).map(|x| TilePos::new(x, 2)).collect::<Vec<_>>();
+        let grid = grid(&walls);
+        let target = TilePos::new(0, 6);
+        let field = grid.flow_field(target, 100);
+
+        for start in [
+            TilePos::new(0, 0),
+            TilePos::new(-15, -10),
+            TilePos::new(12, -4),
+        ] {
+            let path = grid.find_path(start, target, 10_000).unwrap();
+            assert_eq!(field.cost(start), Some(path_cost(&path)));
+
+            let mut pos = start;
+            for _ in 0..100 {
+                pos = field.next(pos).unwrap();
+            }
+            assert_eq!(pos, target);
+        }
+        assert_eq!(field.direction(target), Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn flow_field_without_a_limit() {
+        let grid = grid(&[]);
+        let field = grid.flow_field(TilePos::new(0, 0), u32::MAX);
+        assert_eq!(field.cost(TilePos::new(-20, -20)), Some(20 * DIAGONAL));
+        assert_eq!(
+            field.cost(TilePos::new(19, -20)),
+            Some(19 * DIAGONAL + STRAIGHT)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::hash_unit;
+
+/// Data description of a noise function. It only becomes something that can be sampled once
+/// [`NoiseNode::build`] is given the world seed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NoiseNode {
+    /// `seed` is added to the world seed, so layers can use unrelated Perlin fields.
+    Perlin {
+        seed: u32,
+    },
+    /// Cellular noise, the distance to the nearest feature point mapped to roughly `-1..1`.
+    Worley {
+        seed: u32,
+    },
+    Constant(f64),
+    Fbm(Fractal),
+    Ridged(Fractal),
+    Billow(Fractal),
+    /// Multiplies the input coordinates by `frequency`.
+    Scale {
+        source: Box<NoiseNode>,
+        frequency: f64,
+    },
+    /// Offsets the input coordinates by `warp` times `strength` before sampling `source`.
+    Warp {
+        source: Box<NoiseNode>,
+        warp: Box<NoiseNode>,
+        strength: f64,
+    },
+    Add(Vec<NoiseNode>),
+    Multiply(Vec<NoiseNode>),
+}
+
+/// Octave settings shared by the fractal nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fractal {
+    pub source: Box<NoiseNode>,
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub persistence: f64,
+}
+
+impl NoiseNode {
+    pub fn build(&self, seed: u32) -> NoiseGraph {
+        NoiseGraph(Arc::new(Node::new(self, seed)))
+    }
+
+    pub fn perlin(seed: u32) -> Self {
+        NoiseNode::Perlin { seed }
+    }
+
+    pub fn scale(self, frequency: f64) -> Self {
+        NoiseNode::Scale {
+            source: Box::new(self),
+            frequency,
+        }
+    }
+
+    pub fn fbm(self, octaves: u32, lacunarity: f64, persistence: f64) -> Self {
+        NoiseNode::Fbm(Fractal {
+            source: Box::new(self),
+            octaves,
+            lacunarity,
+            persistence,
+        })
+    }
+
+    pub fn warp(self, warp: NoiseNode, strength: f64) -> Self {
+        NoiseNode::Warp {
+            source: Box::new(self),
+            warp: Box::new(warp),
+            strength,
+        }
+    }
+}
+
+/// A built [`NoiseNode`], cheap to clone and safe to sample from any thread.
+#[derive(Clone)]
+pub struct NoiseGraph(Arc<Node>);
+
+impl NoiseFn<f64, 2> for NoiseGraph {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.0.get(point)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FractalKind {
+    Fbm,
+    Ridged,
+    Billow,
+}
+
+enum Node {
+    Perlin(Box<Perlin>),
+    Worley(u32),
+    Constant(f64),
+    Fractal {
+        kind: FractalKind,
+        source: Box<Node>,
+        octaves: u32,
+        lacunarity: f64,
+        persistence: f64,
+    },
+    Scale(Box<Node>, f64),
+    Warp(Box<Node>, Box<Node>, f64),
+    Add(Vec<Node>),
+    Multiply(Vec<Node>),
+}
+
+/// Shifts each octave so they don't all line up at the origin.
+const OCTAVE_OFFSET: f64 = 19.19;
+/// Where the y offset of a warp is sampled, relative to the x offset.
+const WARP_OFFSET: [f64; 2] = [5.2, 1.3];
+
+impl Node {
+    fn new(node: &NoiseNode, seed: u32) -> Self {
+        let fractal = |kind, fractal: &Fractal| Node::Fractal {
+            kind,
+            source: Box::new(Node::new(&fractal.source, seed)),
+            octaves: fractal.octaves,
+            lacunarity: fractal.lacunarity,
+            persistence: fractal.persistence,
+        };
+        match node {
+            NoiseNode::Perlin { seed: offset } => {
+                Node::Perlin(Box::new(Perlin::new(seed.wrapping_add(*offset))))
+            }
+            NoiseNode::Worley { seed: offset } => Node::Worley(seed.wrapping_add(*offset)),
+            NoiseNode::Constant(value) => Node::Constant(*value),
+            NoiseNode::Fbm(f) => fractal(FractalKind::Fbm, f),
+            NoiseNode::Ridged(f) => fractal(FractalKind::Ridged, f),
+            NoiseNode::Billow(f) => fractal(FractalKind::Billow, f),
+            NoiseNode::Scale { source, frequency } => {
+                Node::Scale(Box::new(Node::new(source, seed)), *frequency)
+            }
+            NoiseNode::Warp {
+                source,
+                warp,
+                strength,
+            } => Node::Warp(
+                Box::new(Node::new(source, seed)),
+                Box::new(Node::new(warp, seed)),
+                *strength,
+            ),
+            NoiseNode::Add(nodes) => Node::Add(nodes.iter().map(|n| Node::new(n, seed)).collect()),
+            NoiseNode::Multiply(nodes) => {
+                Node::Multiply(nodes.iter().map(|n| Node::new(n, seed)).collect())
+            }
+        }
+    }
+
+    fn get(&self, [x, y]: [f64; 2]) -> f64 {
+        match self {
+            Node::Perlin(perlin) => perlin.get([x, y]),
+            Node::Worley(seed) => worley(*seed, x, y),
+            Node::Constant(value) => *value,
+            Node::Fractal {
+                kind,
+                source,
+                octaves,
+                lacunarity,
+                persistence,
+            } => {
+                let (mut sum, mut total) = (0., 0.);
+                let (mut amplitude, mut frequency) = (1., 1.);
+                for octave in 0..*octaves {
+                    let offset = octave as f64 * OCTAVE_OFFSET;
+                    let value = source.get([x * frequency + offset, y * frequency + offset]);
+                    let value = match kind {
+                        FractalKind::Fbm => value,
+                        FractalKind::Ridged => {
+                            let ridge = 1. - value.abs();
+                            ridge * ridge * 2. - 1.
+                        }
+                        FractalKind::Billow => value.abs() * 2. - 1.,
+                    };
+                    sum += value * amplitude;
+                    total += amplitude;
+                    amplitude *= persistence;
+                    frequency *= lacunarity;
+                }
+                if total > 0. {
+                    sum / total
+                } else {
+                    0.
+                }
+            }
+            Node::Scale(source, frequency) => source.get([x * frequency, y * frequency]),
+            Node::Warp(source, warp, strength) => {
+                let dx = warp.get([x, y]);
+                let dy = warp.get([x + WARP_OFFSET[0], y + WARP_OFFSET[1]]);
+                source.get([x + dx * strength, y + dy * strength])
+            }
+            Node::Add(nodes) => nodes.iter().map(|n| n.get([x, y])).sum(),
+            Node::Multiply(nodes) => nodes.iter().map(|n| n.get([x, y])).product(),
+        }
+    }
+}
+
+/// F1 cellular noise with one jittered feature point per unit cell.
+fn worley(seed: u32, x: f64, y: f64) -> f64 {
+    let (cx, cy) = (x.floor() as i32, y.floor() as i32);
+    let mut nearest = f64::MAX;
+    for j in cy - 1..=cy + 1 {
+        for i in cx - 1..=cx + 1 {
+            let px = i as f64 + hash_unit(seed, i, j) as f64;
+            let py = j as f64 + hash_unit(seed ^ 0x9e37_79b9, i, j) as f64;
+            nearest = nearest.min((px - x).powi(2) + (py - y).powi(2));
+        }
+    }
+    (nearest.sqrt() * 2. - 1.).clamp(-1., 1.)
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+
+use crate::{ChunkChanged, Decoration, LoadedChunks, TerrainMap, TileChanged, TilePos};
+
+/// Between the chunk meshes and the entities walking on them.
+const PROP_Z: f32 = 0.5;
+
+type SpawnProp = dyn Fn(&mut EntityCommands, &Decoration, &AssetServer) + Send + Sync;
+
+/// What to spawn for each decoration prop. Props without a prefab aren't spawned.
+#[derive(Resource, Clone, Default)]
+pub struct PropPrefabs(HashMap<String, Arc<SpawnProp>>);
+
+impl PropPrefabs {
+    /// The bundle doesn't need a `Transform`, the prop is placed at its decoration.
+    pub fn insert<B: Bundle>(
+        &mut self,
+        prop: impl Into<String>,
+        prefab: impl Fn(&Decoration, &AssetServer) -> B + Send + Sync + 'static,
+    ) {
+        self.0.insert(
+            prop.into(),
+            Arc::new(move |entity, decoration, assets| {
+                entity.insert(prefab(decoration, assets));
+            }),
+        );
+    }
+}
+
+/// A spawned decoration, a child of its chunk.
+#[derive(Component)]
+pub struct Prop {
+    pub tile: TilePos,
+}
+
+pub fn spawn_props(
+    mut commands: Commands,
+    mut changes: EventReader<ChunkChanged>,
+    map: Res<TerrainMap>,
+    loaded: Res<LoadedChunks>,
+    prefabs: Res<PropPrefabs>,
+    assets: Res<AssetServer>,
+) {
+    for ChunkChanged(coord) in changes.read() {
+        let (Some(data), Some(chunk)) = (map.chunk(*coord), loaded.get(*coord)) else {
+            continue;
+        };
+        commands.entity(chunk).with_children(|parent| {
+            for decoration in data.decorations() {
+                let Some(prefab) = prefabs.0.get(&decoration.prop) else {
+                    continue;
+                };
+                let local = decoration.pos - coord.to_world();
+                let mut entity = parent.spawn(Prop {
+                    tile: decoration.tile,
+                });
+                prefab(&mut entity, decoration, &assets);
+                entity.insert(Transform::from_translation(local.extend(PROP_Z)));
+            }
+        });
+    }
+}
+
+/// Props don't outlive the tile they stand on being edited.
+pub fn remove_edited_props(
+    mut commands: Commands,
+    mut edits: EventReader<TileChanged>,
+    props: Query<(Entity, &Prop)>,
+) {
+    let edited = edits.read().map(|edit| edit.pos).collect::<Vec<_>>();
+    if edited.is_empty() {
+        return;
+    }
+    for (entity, prop) in &props {
+        if edited.contains(&prop.tile) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec2,
+    utils::{HashMap, HashSet},
+};
+
+const NEIGHBOURS: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+
+/// Walls of a rectangle of world tiles, anything that blocks the way. Everything outside counts
+/// as wall.
+pub(crate) struct Grid {
+    origin: IVec2,
+    size: IVec2,
+    walls: Vec<bool>,
+}
+
+impl Grid {
+    pub(crate) fn new(origin: IVec2, size: IVec2, mut wall: impl FnMut(IVec2) -> bool) -> Self {
+        let walls = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| IVec2::new(x, y)))
+            .map(|pos| wall(origin + pos))
+            .collect();
+        Self {
+            origin,
+            size,
+            walls,
+        }
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let local = pos - self.origin;
+        (local.cmpge(IVec2::ZERO).all() && local.cmplt(self.size).all())
+            .then(|| (local.y * self.size.x + local.x) as usize)
+    }
+
+    pub(crate) fn is_wall(&self, pos: IVec2) -> bool {
+        self.index(pos).is_none_or(|i| self.walls[i])
+    }
+
+    pub(crate) fn set_all(&mut self, tiles: &[IVec2], wall: bool) {
+        for pos in tiles {
+            if let Some(i) = self.index(*pos) {
+                self.walls[i] = wall;
+            }
+        }
+    }
+
+    pub(crate) fn smooth(&self, birth: u8, survival: u8) -> Self {
+        Self::new(self.origin, self.size, |pos| {
+            let walls = (-1..=1)
+                .flat_map(|y| (-1..=1).map(move |x| IVec2::new(x, y)))
+                .filter(|offset| *offset != IVec2::ZERO && self.is_wall(pos + offset))
+                .count() as u8;
+            if self.is_wall(pos) {
+                walls >= survival
+            } else {
+                walls >= birth
+            }
+        })
+    }
+
+    /// Open regions inside `window` that are smaller than `min_size` and don't reach its edge,
+    /// each sorted row by row.
+    pub(crate) fn pockets(&self, (min, max): (IVec2, IVec2), min_size: u32) -> Vec<Vec<IVec2>> {
+        let inside = |pos: IVec2| pos.cmpge(min).all() && pos.cmplt(max).all();
+        let mut seen = HashSet::new();
+        let mut pockets = Vec::new();
+
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let start = IVec2::new(x, y);
+                if self.is_wall(start) || seen.contains(&start) {
+                    continue;
+                }
+                let mut region = vec![start];
+                let mut touches_edge = false;
+                seen.insert(start);
+                let mut next = 0;
+                while let Some(&pos) = region.get(next) {
+                    next += 1;
+                    for offset in NEIGHBOURS {
+                        let neighbour = pos + offset;
+                        if !inside(neighbour) {
+                            touches_edge = true;
+                        } else if !self.is_wall(neighbour) && seen.insert(neighbour) {
+                            region.push(neighbour);
+                        }
+                    }
+                }
+                if !touches_edge && (region.len() as u32) < min_size {
+                    region.sort_by_key(|pos| (pos.y, pos.x));
+                    pockets.push(region);
+                }
+            }
+        }
+        pockets
+    }
+
+    /// Shortest path of tiles from `pocket` to an open tile that isn't in a pocket, if one is
+    /// within `max_length` steps.
+    pub(crate) fn tunnel(
+        &self,
+        (min, max): (IVec2, IVec2),
+        pocket: &[IVec2],
+        small: &HashSet<IVec2>,
+        max_length: u32,
+    ) -> Option<Vec<IVec2>> {
+        let inside = |pos: IVec2| pos.cmpge(min).all() && pos.cmplt(max).all();
+        let mut came_from = pocket
+            .iter()
+            .map(|pos| (*pos, None))
+            .collect::<HashMap<IVec2, Option<IVec2>>>();
+        let mut queue = pocket.iter().map(|pos| (*pos, 0)).collect::<VecDeque<_>>();
+
+        while let Some((pos, length)) = queue.pop_front() {
+            if !self.is_wall(pos) && !small.contains(&pos) {
+                let mut path = Vec::new();
+                let mut step = Some(pos);
+                while let Some(tile) = step {
+                    path.push(tile);
+                    step = came_from[&tile];
+                }
+                return Some(path);
+            }
+            if length == max_length {
+                continue;
+            }
+            for offset in NEIGHBOURS {
+                let neighbour = pos + offset;
+                if inside(neighbour) && !came_from.contains_key(&neighbour) {
+                    came_from.insert(neighbour, Some(pos));
+                    queue.push_back((neighbour, length + 1));
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::{
+    image::ImageSampler,
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    autotile, Autotile, ChunkChanged, ChunkCoord, LoadedChunks, TerrainAssets, TerrainConfig,
+    TerrainMap, TileChanged, TilePos, TileType, BLOCK_SIZE,
+};
+
+/// All tile images and tilesets packed into one texture, shared by every chunk mesh.
+#[derive(Resource)]
+pub struct TileAtlas {
+    pub material: Handle<ColorMaterial>,
+    uvs: HashMap<TileType, Rect>,
+    autotiles: HashMap<TileType, Autotile>,
+}
+
+impl TileAtlas {
+    /// The whole image of a tile, which is the full tileset for autotiled ones.
+    pub fn uv(&self, tile: TileType) -> Option<Rect> {
+        self.uvs.get(&tile).copied()
+    }
+
+    /// The part of the image to draw `pos` with, picking the tileset variant that matches its
+    /// neighbours.
+    pub fn tile_uv(&self, map: &TerrainMap, pos: TilePos, tile: TileType) -> Option<Rect> {
+        let uv = self.uv(tile)?;
+        let Some(autotile) = self.autotiles.get(&tile) else {
+            return Some(uv);
+        };
+
+        let neighbours = autotile::neighbours(map, pos, |other| autotile.connects_to(tile, other));
+        let variant = autotile.layout.variant(neighbours);
+        let (columns, rows) = autotile.layout.grid();
+        let size = uv.size() / Vec2::new(columns as f32, rows as f32);
+        let min = uv.min + Vec2::new((variant % columns) as f32, (variant / columns) as f32) * size;
+        Some(Rect::from_corners(min, min + size))
+    }
+}
+
+pub fn build_atlas(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    assets: Res<TerrainAssets>,
+    config: Res<TerrainConfig>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !assets
+        .tiles
+        .values()
+        .all(|handle| server.is_loaded_with_dependencies(handle))
+    {
+        return;
+    }
+
+    let mut builder = TextureAtlasBuilder::default();
+    for handle in assets.tiles.values() {
+        let Some(image) = images.get(handle) else {
+            return;
+        };
+        builder.add_texture(Some(handle.id()), image);
+    }
+    let (layout, sources, mut image) = match builder.build() {
+        Ok(atlas) => atlas,
+        Err(err) => {
+            error!("failed to build tile atlas: {err}");
+            return;
+        }
+    };
+    image.sampler = ImageSampler::nearest();
+
+    let size = layout.size.as_vec2();
+    let uvs = assets
+        .tiles
+        .iter()
+        .filter_map(|(tile, handle)| {
+            let rect = sources.texture_rect(&layout, handle)?.as_rect();
+            Some((*tile, Rect::from_corners(rect.min / size, rect.max / size)))
+        })
+        .collect();
+
+    commands.insert_resource(TileAtlas {
+        material: materials.add(images.add(image)),
+        uvs,
+        autotiles: config
+            .tiles
+            .iter()
+            .filter_map(|(tile, info)| Some((*tile, info.autotile.clone()?)))
+            .collect(),
+    });
+}
+
+/// (Re)builds the mesh of every chunk that changed, holding on to them until the atlas is ready.
+/// Neighbouring chunks are rebuilt too since autotiles along their edges depend on it, edits
+/// only touch them when the tile is on the edge.
+#[allow(clippy::too_many_arguments)]
+pub fn mesh_chunks(
+    mut commands: Commands,
+    mut changes: EventReader<ChunkChanged>,
+    mut edits: EventReader<TileChanged>,
+    mut queued: Local<HashSet<ChunkCoord>>,
+    map: Res<TerrainMap>,
+    loaded: Res<LoadedChunks>,
+    chunks: Query<Option<&Mesh2d>>,
+    atlas: Option<Res<TileAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for ChunkChanged(coord) in changes.read() {
+        queued.extend((-1..=1).flat_map(|y| (-1..=1).map(move |x| coord.offset(x, y))));
+    }
+    for TileChanged { pos, .. } in edits.read() {
+        queued.extend((-1..=1).flat_map(|y| (-1..=1).map(move |x| pos.offset(x, y).chunk())));
+    }
+    let Some(atlas) = atlas else {
+        return;
+    };
+
+    for coord in queued.drain() {
+        let (Some(data), Some(entity)) = (map.chunk(coord), loaded.get(coord)) else {
+            continue;
+        };
+        let mesh = chunks.get(entity).ok().flatten();
+        let tiles = data.tiles().filter_map(|(local, tile)| {
+            Some((local, atlas.tile_uv(&map, coord.tile(local), tile)?))
+        });
+
+        match (chunk_mesh(tiles), mesh) {
+            (Some(new), Some(Mesh2d(handle))) => {
+                meshes.insert(handle, new);
+            }
+            (Some(new), None) => {
+                commands.entity(entity).insert((
+                    Mesh2d(meshes.add(new)),
+                    MeshMaterial2d(atlas.material.clone()),
+                ));
+            }
+            (None, _) => {
+                commands
+                    .entity(entity)
+                    .remove::<(Mesh2d, MeshMaterial2d<ColorMaterial>)>();
+            }
+        }
+    }
+}
+
+/// One quad per tile, positioned relative to the chunk origin.
+fn chunk_mesh(tiles: impl Iterator<Item = (IVec2, Rect)>) -> Option<Mesh> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+
+    for (tile, uv) in tiles {
+        let min = tile.as_vec2() * BLOCK_SIZE;
+        let max = min + BLOCK_SIZE;
+        let base = positions.len() as u32;
+        positions.extend([
+            [min.x, min.y, 0.],
+            [max.x, min.y, 0.],
+            [max.x, max.y, 0.],
+            [min.x, max.y, 0.],
+        ]);
+        uvs.extend([
+            [uv.min.x, uv.max.y],
+            [uv.max.x, uv.max.y],
+            [uv.max.x, uv.min.y],
+            [uv.min.x, uv.min.y],
+        ]);
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (!positions.is_empty()).then(|| {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+    })
+}
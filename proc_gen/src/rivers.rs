@@ -0,0 +1,272 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    math::IVec2,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generator::is_water, scatter::scatter, ChunkCoord, NoiseGenerator, TilePos, TileType, CHUNK_X,
+    CHUNK_Y,
+};
+
+/// Settings for rivers, see [`TerrainConfig::rivers`](crate::TerrainConfig::rivers).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiverConfig {
+    /// Least distance in tiles between two springs.
+    pub spacing: f32,
+    /// Springs only rise this high up or higher.
+    pub source_height: f32,
+    /// Rivers only look this many tiles away from their spring for water, and are dropped if
+    /// there is none. Also how far chunks look for springs.
+    pub max_length: u32,
+    pub tile: TileType,
+}
+
+impl Default for RiverConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 24.,
+            source_height: 0.65,
+            max_length: 100,
+            tile: TileType::ShallowWater,
+        }
+    }
+}
+
+/// More than enough for the chunks around a few loaders.
+const CACHE_SIZE: usize = 16384;
+
+/// Rivers run from springs scattered over high ground to the nearest water downhill, flooding
+/// outwards from the lowest tile reached so far the way water fills a dip until it spills over
+/// its lowest edge. Only depending on the spring means every chunk a river crosses traces it the
+/// same.
+pub(crate) struct Rivers {
+    seed: u32,
+    config: RiverConfig,
+    springs: Mutex<HashMap<ChunkCoord, Arc<Vec<TilePos>>>>,
+    paths: Mutex<HashMap<TilePos, Arc<Vec<TilePos>>>>,
+}
+
+impl Rivers {
+    pub(crate) fn new(seed: u32, config: &RiverConfig) -> Self {
+        Self {
+            seed,
+            config: config.clone(),
+            springs: Mutex::default(),
+            paths: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn tile(&self) -> TileType {
+        self.config.tile
+    }
+
+    /// River tiles inside `coord`.
+    pub(crate) fn tiles(&self, generator: &NoiseGenerator, coord: ChunkCoord) -> HashSet<TilePos> {
+        let length = self.config.max_length as i32;
+        let reach = (length + CHUNK_X.min(CHUNK_Y) - 1) / CHUNK_X.min(CHUNK_Y);
+        let min = coord.origin().as_ivec2();
+        let max = min + IVec2::new(CHUNK_X, CHUNK_Y);
+
+        let mut tiles = HashSet::new();
+        for y in coord.y - reach..=coord.y + reach {
+            for x in coord.x - reach..=coord.x + reach {
+                for spring in self.springs(generator, ChunkCoord::new(x, y)).iter() {
+                    let pos = spring.as_ivec2();
+                    // a river can't get further from its spring than it is long
+                    if (pos - max + 1).max_element() > length || (min - pos).max_element() > length
+                    {
+                        continue;
+                    }
+                    let path = self.path(generator, *spring);
+                    tiles.extend(path.iter().filter(|tile| tile.chunk() == coord));
+                }
+            }
+        }
+        tiles
+    }
+
+    fn springs(&self, generator: &NoiseGenerator, coord: ChunkCoord) -> Arc<Vec<TilePos>> {
+        cached(&self.springs, coord, || {
+            scatter(self.seed, coord, self.config.spacing, |point| {
+                generator.height(TilePos::from(point.floor().as_ivec2()))
+                    >= self.config.source_height
+            })
+            .into_iter()
+            .map(|point| TilePos::from(point.floor().as_ivec2()))
+            .collect()
+        })
+    }
+
+    /// Tiles from `spring` to the first water tile, empty if there's none close enough.
+    fn path(&self, generator: &NoiseGenerator, spring: TilePos) -> Arc<Vec<TilePos>> {
+        cached(&self.paths, spring, || {
+            let length = self.config.max_length as i32;
+            let mut came_from = HashMap::from([(spring, spring)]);
+            let mut open = BinaryHeap::from([Reverse((Height(generator.height(spring)), spring))]);
+            while let Some(Reverse((_, pos))) = open.pop() {
+                if is_water(generator.tile(pos)) {
+                    let mut path = vec![pos];
+                    let mut pos = pos;
+                    while pos != spring {
+                        pos = came_from[&pos];
+                        path.push(pos);
+                    }
+                    path.reverse();
+                    return path;
+                }
+                for next in [(1, 0), (-1, 0), (0, 1), (0, -1)].map(|(x, y)| pos.offset(x, y)) {
+                    let far = (next.as_ivec2() - spring.as_ivec2()).abs().max_element() > length;
+                    if far || came_from.contains_key(&next) {
+                        continue;
+                    }
+                    came_from.insert(next, pos);
+                    open.push(Reverse((Height(generator.height(next)), next)));
+                }
+            }
+            Vec::new()
+        })
+    }
+}
+
+/// Heights ordered with [`f32::total_cmp`] so they can go in a heap.
+struct Height(f32);
+
+impl PartialEq for Height {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Height {}
+
+impl PartialOrd for Height {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Height {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn cached<K: Eq + std::hash::Hash + Copy>(
+    cache: &Mutex<HashMap<K, Arc<Vec<TilePos>>>>,
+    key: K,
+    compute: impl FnOnce() -> Vec<TilePos>,
+) -> Arc<Vec<TilePos>> {
+    if let Some(value) = cache.lock().unwrap().get(&key) {
+        return value.clone();
+    }
+    let value = Arc::new(compute());
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, value.clone());
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::TerrainConfig;
+
+    fn generate() -> (NoiseGenerator, Rivers) {
+        let config = TerrainConfig::default();
+        let rivers = RiverConfig {
+            spacing: 12.,
+            ..RiverConfig::default()
+        };
+        (NoiseGenerator::new(2, &config), Rivers::new(9, &rivers))
+    }
+
+    /// Once the dips along the way are filled up to where they spill over, a river only runs
+    /// downhill or level. So nothing on its path is higher than the lowest pass out of the basin
+    /// around its spring.
+    #[test]
+    fn rivers_climb_no_higher_than_they_have_to() {
+        let (generator, rivers) = generate();
+        let length = rivers.config.max_length as i32;
+        let springs = (-4..4)
+            .flat_map(|y| (-4..4).map(move |x| ChunkCoord::new(x, y)))
+            .flat_map(|coord| rivers.springs(&generator, coord).to_vec())
+            .collect::<Vec<_>>();
+
+        let mut checked = 0;
+        for spring in springs {
+            let path = rivers.path(&generator, spring);
+            let Some(mouth) = path.last() else {
+                continue;
+            };
+            checked += 1;
+            assert_eq!(path[0], spring);
+            assert!(is_water(generator.tile(*mouth)));
+            assert!(path[..path.len() - 1]
+                .iter()
+                .all(|pos| !is_water(generator.tile(*pos))));
+            for step in path.windows(2) {
+                let offset = step[1].as_ivec2() - step[0].as_ivec2();
+                assert_eq!(offset.abs().element_sum(), 1);
+            }
+
+            let spill = path
+                .iter()
+                .map(|pos| generator.height(*pos))
+                .fold(f32::MIN, f32::max);
+            if spill == generator.height(spring) {
+                continue;
+            }
+            // staying below the spill point never gets to the water
+            let mut seen = HashSet::from_iter([spring]);
+            let mut queue = VecDeque::from([spring]);
+            while let Some(pos) = queue.pop_front() {
+                assert!(!is_water(generator.tile(pos)), "{spring:?} spills too high");
+                for next in [(1, 0), (-1, 0), (0, 1), (0, -1)].map(|(x, y)| pos.offset(x, y)) {
+                    let far = (next.as_ivec2() - spring.as_ivec2()).abs().max_element() > length;
+                    if !far && generator.height(next) < spill && seen.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn chunks_trace_the_same_rivers() {
+        let (generator, rivers) = generate();
+        let coords = (-3..3).flat_map(|y| (-3..3).map(move |x| ChunkCoord::new(x, y)));
+        let tiles = coords
+            .flat_map(|coord| rivers.tiles(&generator, coord))
+            .collect::<HashSet<_>>();
+        assert!(!tiles.is_empty());
+        // every tile of a path reaching into the area is found by the chunk it's in
+        let (other_generator, other) = generate();
+        for y in -3..3 {
+            for x in -3..3 {
+                for spring in other
+                    .springs(&other_generator, ChunkCoord::new(x, y))
+                    .iter()
+                {
+                    for pos in other.path(&other_generator, *spring).iter() {
+                        let inside =
+                            (-3..3).contains(&pos.chunk().x) && (-3..3).contains(&pos.chunk().y);
+                        assert!(!inside || tiles.contains(pos), "{pos:?}");
+                    }
+                }
+            }
+        }
+    }
+}
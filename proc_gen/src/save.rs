@@ -0,0 +1,177 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{Regenerate, TerrainConfig, TerrainConfigHandle, TerrainEdits, WorldSeed};
+
+/// Bumped whenever a change to the save format means older saves can't be read anymore.
+pub const SAVE_VERSION: u32 = 1;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Everything needed to rebuild a world: chunks are generated again from the seed and config,
+/// then the edits are put back on top.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldSave {
+    pub version: u32,
+    pub seed: u32,
+    pub config: TerrainConfig,
+    pub edits: TerrainEdits,
+}
+
+impl WorldSave {
+    pub fn new(seed: u32, config: TerrainConfig, edits: TerrainEdits) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            seed,
+            config,
+            edits,
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String, Error> {
+        Ok(ron::ser::to_string_pretty(self, default())?)
+    }
+
+    /// Reads a save, rejecting other versions before looking at the rest and configs that
+    /// don't pass [`TerrainConfig::validate`].
+    pub fn from_ron(text: &str) -> Result<Self, Error> {
+        let Header { version } = ron::from_str(text)?;
+        if version != SAVE_VERSION {
+            return Err(
+                format!("save version {version} isn't supported, expected {SAVE_VERSION}").into(),
+            );
+        }
+        let save: WorldSave = ron::from_str(text)?;
+        save.config.validate()?;
+        Ok(save)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+/// Only the version of a [`WorldSave`], everything else is skipped.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Writes the current world to a file.
+#[derive(Event, Debug, Clone)]
+pub struct SaveWorld(pub PathBuf);
+
+/// Replaces the current world with the one saved in a file. The [`TerrainConfigHandle`] is
+/// removed, so the config asset loading or changing later doesn't replace the saved world.
+#[derive(Event, Debug, Clone)]
+pub struct LoadWorld(pub PathBuf);
+
+pub fn save_world(
+    mut events: EventReader<SaveWorld>,
+    seed: Res<WorldSeed>,
+    config: Res<TerrainConfig>,
+    edits: Res<TerrainEdits>,
+) {
+    for SaveWorld(path) in events.read() {
+        let save = WorldSave::new(seed.0, config.clone(), edits.clone());
+        match save.write(path) {
+            Ok(()) => info!("saved world to {}", path.display()),
+            Err(err) => error!("failed to save world to {}: {err}", path.display()),
+        }
+    }
+}
+
+pub fn load_world(
+    mut commands: Commands,
+    mut events: EventReader<LoadWorld>,
+    mut edits: ResMut<TerrainEdits>,
+    mut regenerate: Regenerate,
+) {
+    // only the last one matters, each load replaces the whole world
+    let Some(LoadWorld(path)) = events.read().last() else {
+        return;
+    };
+    match WorldSave::read(path) {
+        Ok(save) => {
+            info!("loaded world from {}", path.display());
+            commands.remove_resource::<TerrainConfigHandle>();
+            *edits = save.edits;
+            regenerate.run(save.seed, &save.config);
+        }
+        Err(err) => error!("failed to load world from {}: {err}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChunkCoord, NoiseGenerator, TerrainGenerator, TilePos, TileType};
+
+    fn save() -> WorldSave {
+        let edits: TerrainEdits = ron::from_str(
+            "(chunks: {(x: -1, y: 0): {(x: -3, y: 4): Rock}}, \
+              deposits: {(x: 0, y: 0): {(x: 1, y: 2): 0}})",
+        )
+        .unwrap();
+        WorldSave::new(42, TerrainConfig::default(), edits)
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let save = save();
+        let text = save.to_ron().unwrap();
+        let loaded = WorldSave::from_ron(&text).unwrap();
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.edits.get(TilePos::new(-3, 4)), Some(TileType::Rock));
+        assert_eq!(loaded.edits.chunk(ChunkCoord::new(0, 0)).count(), 0);
+
+        // the same world comes back
+        let before = NoiseGenerator::new(save.seed, &save.config);
+        let after = NoiseGenerator::new(loaded.seed, &loaded.config);
+        for coord in [ChunkCoord::new(-1, 0), ChunkCoord::new(0, 0)] {
+            let (mut before, mut after) =
+                (before.generate_chunk(coord), after.generate_chunk(coord));
+            save.edits.apply(&mut before);
+            loaded.edits.apply(&mut after);
+            for tile in coord.tiles() {
+                let local = tile.local();
+                assert_eq!(before.tile(local), after.tile(local));
+                assert_eq!(before.deposit(local), after.deposit(local));
+                assert_eq!(before.deposit(local), after.deposit(local));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut save = save();
+        save.version = SAVE_VERSION + 1;
+        let err = WorldSave::from_ron(&save.to_ron().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("isn't supported"), "{err}");
+
+        // even when the rest of it looks nothing like a save anymore
+        let err = WorldSave::from_ron("(version: 0, world: [1, 2, 3])").unwrap_err();
+        assert!(err.to_string().contains("isn't supported"), "{err}");
+    }
+
+    #[test]
+    fn rejects_broken_configs() {
+        let mut ragged = save();
+        ragged.config.table.rows[1].pop();
+        let err = WorldSave::from_ron(&ragged.to_ron().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("biome table"), "{err}");
+
+        let mut crowded = save();
+        crowded.config.structures[0].spacing = 0.;
+        assert!(WorldSave::from_ron(&crowded.to_ron().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::f32::consts::SQRT_2;
+
+use bevy::{math::Vec2, utils::HashMap};
+
+use crate::{hash, hash_unit, ChunkCoord, CHUNK_X, CHUNK_Y};
+
+/// Poisson-disk points inside `coord`, in tile units, with at least `spacing` tiles between
+/// them. `valid` filters out points that shouldn't be used. Spacings that aren't positive get no
+/// points at all.
+///
+/// Every cell of a world-wide grid gets one candidate with a random priority, and a candidate is
+/// kept if no valid candidate with a higher priority is within `spacing`. Everything is hashed
+/// from the cell, so chunks agree on the points near their shared borders without talking to
+/// each other.
+pub(crate) fn scatter(
+    seed: u32,
+    coord: ChunkCoord,
+    spacing: f32,
+    valid: impl Fn(Vec2) -> bool,
+) -> Vec<Vec2> {
+    if spacing.is_nan() || spacing <= 0. {
+        return Vec::new();
+    }
+    let cell = spacing / SQRT_2;
+    let min = coord.origin().as_ivec2().as_vec2();
+    let max = min + Vec2::new(CHUNK_X as f32, CHUNK_Y as f32);
+    let first = (min / cell).floor().as_ivec2();
+    let last = (max / cell).ceil().as_ivec2();
+
+    let mut checked = HashMap::new();
+    let mut is_valid = |x: i32, y: i32| {
+        *checked
+            .entry((x, y))
+            .or_insert_with(|| valid(candidate(seed, cell, x, y)))
+    };
+
+    let mut points = Vec::new();
+    for y in first.y..last.y {
+        for x in first.x..last.x {
+            let point = candidate(seed, cell, x, y);
+            if point.cmplt(min).any() || point.cmpge(max).any() || !is_valid(x, y) {
+                continue;
+            }
+            // with cells this size anything within `spacing` is at most two cells away
+            let beaten = (-2..=2)
+                .flat_map(|j| (-2..=2).map(move |i| (x + i, y + j)))
+                .filter(|&other| other != (x, y))
+                .any(|(i, j)| {
+                    candidate(seed, cell, i, j).distance(point) < spacing
+                        && priority(seed, i, j) > priority(seed, x, y)
+                        && is_valid(i, j)
+                });
+            if !beaten {
+                points.push(point);
+            }
+        }
+    }
+    points
+}
+
+fn candidate(seed: u32, cell: f32, x: i32, y: i32) -> Vec2 {
+    let jitter = Vec2::new(hash_unit(seed, x, y), hash_unit(seed ^ 0x68e3_1da4, x, y));
+    (Vec2::new(x as f32, y as f32) + jitter) * cell
+}
+
+/// Ties are broken by cell so two candidates never beat each other.
+fn priority(seed: u32, x: i32, y: i32) -> (u32, i32, i32) {
+    (hash(seed ^ 0xb529_7a4d, x, y), x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spacing_holds_across_chunks() {
+        let spacing = 3.;
+        let points = (-2..2)
+            .flat_map(|y| (-2..2).map(move |x| ChunkCoord::new(x, y)))
+            .flat_map(|coord| scatter(7, coord, spacing, |_| true))
+            .collect::<Vec<_>>();
+        assert!(points.len() > 20);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) >= spacing, "{a} and {b} are too close");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_points_dont_block_others() {
+        let coord = ChunkCoord::new(0, 0);
+        let all = scatter(3, coord, 4., |_| true);
+        let left = scatter(3, coord, 4., |point| point.x < 5.);
+        assert!(left.iter().all(|point| point.x < 5.));
+        // points kept everywhere stay kept with fewer competitors
+        for point in all.iter().filter(|point| point.x < 5.) {
+            assert!(left.contains(point));
+        }
+    }
+
+    #[test]
+    fn no_points_without_spacing() {
+        for spacing in [0., -1., f32::NAN] {
+            assert!(scatter(0, ChunkCoord::new(0, 0), spacing, |_| true).is_empty());
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use bevy::{math::IVec2, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{hash_unit, scatter::scatter, Biome, ChunkCoord, TilePos, TileType, CHUNK_X, CHUNK_Y};
+
+/// An authored tile map stamped into the terrain, like ruins or a village. `rows` go from top to
+/// bottom and `legend` says which tile each character is, characters missing from it keep the
+/// terrain underneath.
+///
+/// Structures keep at least `spacing` tiles between the bottom left corners of any two of them,
+/// and a spot that fits gets one with a `chance`. A spot fits when the middle of the structure
+/// is in one of the `biomes` and every tile under it is one of `ground`, with empty lists
+/// allowing anything, and its heights vary by no more than `max_height_range`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Structure {
+    pub name: String,
+    pub rows: Vec<String>,
+    pub legend: HashMap<char, TileType>,
+    pub spacing: f32,
+    #[serde(default = "always")]
+    pub chance: f32,
+    #[serde(default)]
+    pub biomes: Vec<Biome>,
+    #[serde(default)]
+    pub ground: Vec<TileType>,
+    #[serde(default)]
+    pub max_height_range: Option<f32>,
+}
+
+fn always() -> f32 {
+    1.
+}
+
+impl Structure {
+    pub(crate) fn new(name: &str, rows: &[&str], legend: &[(char, TileType)]) -> Self {
+        Self {
+            name: name.into(),
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+            legend: legend.iter().copied().collect(),
+            spacing: 64.,
+            chance: 1.,
+            biomes: Vec::new(),
+            ground: Vec::new(),
+            max_height_range: None,
+        }
+    }
+
+    pub(crate) fn spacing(mut self, spacing: f32, chance: f32) -> Self {
+        self.spacing = spacing;
+        self.chance = chance;
+        self
+    }
+
+    pub(crate) fn biomes(mut self, biomes: &[Biome]) -> Self {
+        self.biomes = biomes.to_vec();
+        self
+    }
+
+    pub(crate) fn ground(mut self, tiles: &[TileType]) -> Self {
+        self.ground = tiles.to_vec();
+        self
+    }
+
+    pub(crate) fn max_height_range(mut self, range: f32) -> Self {
+        self.max_height_range = Some(range);
+        self
+    }
+
+    pub fn size(&self) -> IVec2 {
+        let width = self.rows.iter().map(|row| row.chars().count()).max();
+        IVec2::new(width.unwrap_or(0) as i32, self.rows.len() as i32)
+    }
+
+    /// Tiles the structure sets, relative to its bottom left corner.
+    pub fn tiles(&self) -> impl Iterator<Item = (IVec2, TileType)> + '_ {
+        let height = self.rows.len() as i32;
+        self.rows.iter().enumerate().flat_map(move |(row, chars)| {
+            chars.chars().enumerate().filter_map(move |(x, c)| {
+                let pos = IVec2::new(x as i32, height - 1 - row as i32);
+                Some((pos, *self.legend.get(&c)?))
+            })
+        })
+    }
+}
+
+/// Bottom left corners of the structures overlapping `coord`. Corners are scattered over the
+/// chunks they lie in, so every chunk a structure covers finds the same one.
+pub(crate) fn placements(
+    seed: u32,
+    structure: &Structure,
+    coord: ChunkCoord,
+    fits: impl Fn(TilePos) -> bool,
+) -> Vec<TilePos> {
+    let size = structure.size();
+    let min = coord.origin().as_ivec2();
+    let max = min + IVec2::new(CHUNK_X, CHUNK_Y);
+    let first = TilePos::from(min - size + 1).chunk();
+
+    let mut corners = Vec::new();
+    for y in first.y..=coord.y {
+        for x in first.x..=coord.x {
+            let points = scatter(seed, ChunkCoord::new(x, y), structure.spacing, |point| {
+                let corner = TilePos::from(point.floor().as_ivec2());
+                hash_unit(seed ^ 0x2c1b_3c6d, corner.x, corner.y) < structure.chance && fits(corner)
+            });
+            corners.extend(
+                points
+                    .into_iter()
+                    .map(|point| point.floor().as_ivec2())
+                    .filter(|corner| (*corner + size).cmpgt(min).all() && corner.cmplt(max).all())
+                    .map(TilePos::from),
+            );
+        }
+    }
+    corners
+}
+
+pub(crate) fn default_structures() -> Vec<Structure> {
+    use TileType::*;
+    vec![
+        Structure::new(
+            "ruins",
+            &[
+                "RR RRRR  RR",
+                "R#######  R",
+                "R##R###R##R",
+                " ##########",
+                "R####### #R",
+                "R##R###R##R",
+                "R#########R",
+                "RRRR  RRRRR",
+            ],
+            &[('R', Rock), ('#', Sand)],
+        )
+        .spacing(48., 0.6)
+        .biomes(&[Biome::Grassland, Biome::Woodland, Biome::Savanna])
+        .ground(&[Sand, Grass, Forest, Rock])
+        .max_height_range(0.3),
+        Structure::new(
+            "oasis",
+            &[
+                " ##### ", "##~~~##", "#~~~~~#", "#~~~~~#", "##~~~##", " ##### ",
+            ],
+            &[('~', ShallowWater), ('#', Grass)],
+        )
+        .spacing(40., 0.8)
+        .biomes(&[Biome::Desert])
+        .ground(&[Sand]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NoiseGenerator, TerrainConfig, TerrainGenerator};
+
+    fn hut() -> Structure {
+        Structure::new(
+            "hut",
+            &["RRRR", "R##R", "R##R", "RRRR"],
+            &[('R', TileType::Rock), ('#', TileType::Sand)],
+        )
+        .spacing(12., 1.)
+    }
+
+    #[test]
+    fn every_chunk_a_structure_covers_finds_it() {
+        let hut = hut();
+        let size = hut.size();
+        let coords = (-3..3).flat_map(|y| (-3..3).map(move |x| ChunkCoord::new(x, y)));
+        let found = coords
+            .map(|coord| (coord, placements(5, &hut, coord, |_| true)))
+            .collect::<HashMap<_, _>>();
+
+        let mut straddling = 0;
+        for (coord, corners) in &found {
+            for corner in corners {
+                let (first, last) = (
+                    corner.chunk(),
+                    corner.offset(size.x - 1, size.y - 1).chunk(),
+                );
+                straddling += (first != last) as u32;
+                for y in first.y..=last.y {
+                    for x in first.x..=last.x {
+                        let other = ChunkCoord::new(x, y);
+                        let seen = found.get(&other).is_none_or(|c| c.contains(corner));
+                        assert!(seen, "{other:?} misses {corner:?} found by {coord:?}");
+                    }
+                }
+            }
+        }
+        assert!(straddling > 0);
+    }
+
+    #[test]
+    fn stamped_the_same_in_any_order() {
+        let config = TerrainConfig {
+            structures: vec![hut()],
+            ..TerrainConfig::default()
+        };
+        let (a, b) = (
+            NoiseGenerator::new(3, &config),
+            NoiseGenerator::new(3, &config),
+        );
+        let coords =
+            [(0, 0), (-1, 0), (0, -1), (-1, -1), (4, 2)].map(|(x, y)| ChunkCoord::new(x, y));
+        let first = coords.map(|coord| a.generate_chunk(coord).tiles().collect::<Vec<_>>());
+        let second = coords
+            .into_iter()
+            .rev()
+            .map(|coord| b.generate_chunk(coord).tiles().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert!(first.iter().eq(second.iter().rev()));
+    }
+}
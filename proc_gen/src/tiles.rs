@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Autotile, TilesetLayout};
+
+#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum TileType {
+    DeepWater,
+    ShallowWater,
+    Sand,
+    Grass,
+    Forest,
+    Rock,
+    Snow,
+}
+
+/// `walkable` tiles are fine to stand on, `solid` tiles block movement entirely. With an
+/// `autotile` the tileset is drawn instead of `texture`. Tiles with `dig_to` can be dug out,
+/// leaving that tile behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileInfo {
+    pub texture: String,
+    pub walkable: bool,
+    pub solid: bool,
+    #[serde(default)]
+    pub autotile: Option<Autotile>,
+    #[serde(default)]
+    pub dig_to: Option<TileType>,
+}
+
+impl TileInfo {
+    pub(crate) fn new(texture: &str, walkable: bool, solid: bool) -> Self {
+        Self {
+            texture: texture.into(),
+            walkable,
+            solid,
+            autotile: None,
+            dig_to: None,
+        }
+    }
+
+    pub(crate) fn with_autotile(mut self, tileset: &str, layout: TilesetLayout) -> Self {
+        self.autotile = Some(Autotile {
+            tileset: tileset.into(),
+            layout,
+            connects: Vec::new(),
+        });
+        self
+    }
+
+    pub(crate) fn with_dig_to(mut self, tile: TileType) -> Self {
+        self.dig_to = Some(tile);
+        self
+    }
+}
+
+/// Every height up to and including `max` that isn't claimed by an earlier band becomes `tile`.
+/// Heights above the last band use the last band's tile.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeightBand {
+    pub max: f32,
+    pub tile: TileType,
+}
+
+pub(crate) fn tile_for(bands: &[HeightBand], height: f32) -> TileType {
+    bands
+        .iter()
+        .find(|band| height <= band.max)
+        .or(bands.last())
+        .map_or(TileType::Grass, |band| band.tile)
+}
@@ -0,0 +1,580 @@
+use bevy::{image::Image, math::IVec2, render::render_resource::TextureFormat, utils::HashMap};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash, ChunkCoord, ChunkData, NoiseGenerator, TerrainConfig, TerrainGenerator, TileType,
+    CHUNK_X, CHUNK_Y,
+};
+
+/// Settings for [`WfcGenerator`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WfcConfig {
+    pub source: WfcSource,
+    /// How many times a piece is restarted with a new seed after running into a contradiction.
+    pub max_retries: u32,
+    /// Used where nothing fits in a piece that still can't be solved after all retries.
+    pub fallback: TileType,
+}
+
+impl Default for WfcConfig {
+    fn default() -> Self {
+        let legend = [
+            ('.', TileType::Grass),
+            ('#', TileType::Sand),
+            ('R', TileType::Rock),
+            ('T', TileType::Forest),
+            ('~', TileType::ShallowWater),
+        ];
+        Self {
+            source: WfcSource::Sample {
+                rows: [
+                    "..TT......~~~...",
+                    ".TT..RRRR..~~...",
+                    ".....R##R.......",
+                    "..#########..T..",
+                    "..#..R##R.#.TT..",
+                    "..#..RRRR.#.....",
+                    "..#.......#.....",
+                    "RRRRR..RRRRR..T.",
+                    "R###R..R###R....",
+                    "R###R..R###R.~~.",
+                    "RR#RR..RR#RR.~~.",
+                    "..#######.#.....",
+                ]
+                .map(String::from)
+                .to_vec(),
+                legend: legend.into_iter().collect(),
+            },
+            max_retries: 10,
+            fallback: TileType::Grass,
+        }
+    }
+}
+
+/// Where the adjacency rules come from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WfcSource {
+    /// Rows of characters from top to bottom, with `legend` saying which tile each one is. Tiles
+    /// may only be next to each other the way they are somewhere in the sample, and are picked
+    /// as often as they appear in it.
+    Sample {
+        rows: Vec<String>,
+        legend: HashMap<char, TileType>,
+    },
+    /// Pairs of tiles that may be next to each other in any direction. Tiles without a weight
+    /// get a weight of one.
+    Rules {
+        adjacent: Vec<(TileType, TileType)>,
+        weights: HashMap<TileType, f32>,
+    },
+}
+
+/// +x, +y, -x, -y
+const DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+
+fn opposite(direction: usize) -> usize {
+    (direction + 2) % 4
+}
+
+/// The `size` tiles starting at `min`, row by row.
+fn cells(min: IVec2, size: IVec2) -> impl Iterator<Item = IVec2> {
+    (0..size.y).flat_map(move |y| (0..size.x).map(move |x| min + IVec2::new(x, y)))
+}
+
+/// Which tiles may be next to which, learned from a sample or written by hand. Sets of tiles
+/// are bit masks over `tiles`, which is fine as long as there are fewer than 64 tile types.
+#[derive(Debug, Clone, Default)]
+pub struct WfcRules {
+    tiles: Vec<TileType>,
+    weights: Vec<f32>,
+    /// For each direction and tile, the tiles allowed next to it in that direction.
+    allowed: [Vec<u64>; 4],
+}
+
+impl WfcRules {
+    /// `grid` is indexed `[y][x]` with y going up.
+    pub fn from_sample(grid: &[Vec<Option<TileType>>]) -> Self {
+        let mut rules = Self::default();
+        let at = |pos: IVec2| {
+            grid.get(usize::try_from(pos.y).ok()?)?
+                .get(usize::try_from(pos.x).ok()?)
+                .copied()
+                .flatten()
+        };
+        for (y, row) in grid.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let Some(tile) = *tile else {
+                    continue;
+                };
+                let index = rules.index_or_insert(tile);
+                rules.weights[index] += 1.;
+                let pos = IVec2::new(x as i32, y as i32);
+                for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                    if let Some(other) = at(pos + *offset) {
+                        rules.allow(tile, direction, other);
+                    }
+                }
+            }
+        }
+        rules
+    }
+
+    /// Learns from an RGBA8 image, one pixel per tile. Pixels missing from `palette` are
+    /// ignored. Fails on images it can't learn anything from: other formats, ones without two
+    /// pixels next to each other, and ones with no pixel in the palette.
+    pub fn from_image(image: &Image, palette: &HashMap<[u8; 3], TileType>) -> Result<Self, String> {
+        let format = image.texture_descriptor.format;
+        if !matches!(
+            format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) {
+            return Err(format!("the sample image is {format:?}, expected RGBA8"));
+        }
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 || width * height < 2 {
+            return Err(format!(
+                "the sample image is {width}x{height}, it needs two pixels next to each other"
+            ));
+        }
+        if image.data.len() != 4 * width * height {
+            return Err(format!(
+                "the sample image is {width}x{height} but has {} bytes",
+                image.data.len()
+            ));
+        }
+        let grid = image
+            .data
+            .chunks_exact(4 * width)
+            .rev()
+            .map(|row| {
+                row.chunks_exact(4)
+                    .map(|pixel| palette.get(&[pixel[0], pixel[1], pixel[2]]).copied())
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        let rules = Self::from_sample(&grid);
+        if rules.tiles.is_empty() {
+            return Err("none of the sample image's colors are in the palette".into());
+        }
+        Ok(rules)
+    }
+
+    pub fn from_adjacent(
+        adjacent: &[(TileType, TileType)],
+        weights: &HashMap<TileType, f32>,
+    ) -> Self {
+        let mut rules = Self::default();
+        for (a, b) in adjacent {
+            for direction in 0..4 {
+                rules.allow(*a, direction, *b);
+                rules.allow(*b, direction, *a);
+            }
+        }
+        for (tile, weight) in rules.tiles.iter().zip(&mut rules.weights) {
+            *weight = weights.get(tile).copied().unwrap_or(1.);
+        }
+        rules
+    }
+
+    pub fn from_source(source: &WfcSource) -> Self {
+        match source {
+            WfcSource::Sample { rows, legend } => {
+                let grid = rows
+                    .iter()
+                    .rev()
+                    .map(|row| row.chars().map(|c| legend.get(&c).copied()).collect())
+                    .collect::<Vec<_>>();
+                Self::from_sample(&grid)
+            }
+            WfcSource::Rules { adjacent, weights } => Self::from_adjacent(adjacent, weights),
+        }
+    }
+
+    /// Lets `b` be next to `a` in `direction`, and `a` next to `b` the other way.
+    pub fn allow(&mut self, a: TileType, direction: usize, b: TileType) {
+        let (a, b) = (self.index_or_insert(a), self.index_or_insert(b));
+        self.allowed[direction][a] |= 1 << b;
+        self.allowed[opposite(direction)][b] |= 1 << a;
+    }
+
+    fn index_or_insert(&mut self, tile: TileType) -> usize {
+        if let Some(index) = self.tiles.iter().position(|t| *t == tile) {
+            return index;
+        }
+        self.tiles.push(tile);
+        self.weights.push(0.);
+        for allowed in &mut self.allowed {
+            allowed.push(0);
+        }
+        self.tiles.len() - 1
+    }
+
+    fn all(&self) -> u64 {
+        (1u64 << self.tiles.len()) - 1
+    }
+
+    fn index(&self, tile: TileType) -> Option<usize> {
+        self.tiles.iter().position(|t| *t == tile)
+    }
+
+    /// Tiles allowed next to any of `tiles` in `direction`.
+    fn neighbours(&self, tiles: u64, direction: usize) -> u64 {
+        (0..self.tiles.len())
+            .filter(|i| tiles & 1 << i != 0)
+            .fold(0, |mask, i| mask | self.allowed[direction][i])
+    }
+
+    /// Collapses the `size` tiles starting at `min`, next to the already decided tiles in
+    /// `fixed`. Returns `None` on a contradiction.
+    fn solve(
+        &self,
+        min: IVec2,
+        size: IVec2,
+        fixed: &HashMap<IVec2, TileType>,
+        seed: u64,
+    ) -> Option<HashMap<IVec2, TileType>> {
+        let inside = |pos: IVec2| pos.cmpge(min).all() && pos.cmplt(min + size).all();
+        let index = |pos: IVec2| ((pos.y - min.y) * size.x + pos.x - min.x) as usize;
+        let cells = cells(min, size).collect::<Vec<_>>();
+        let mut domains = vec![self.all(); cells.len()];
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for (i, pos) in cells.iter().enumerate() {
+            for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                let Some(tile) = fixed.get(&(*pos + *offset)) else {
+                    continue;
+                };
+                // an unknown tile next to us can't be matched by anything
+                let allowed = self
+                    .index(*tile)
+                    .map_or(0, |tile| self.allowed[opposite(direction)][tile]);
+                domains[i] &= allowed;
+            }
+        }
+
+        let mut stack = (0..cells.len()).collect::<Vec<_>>();
+        loop {
+            while let Some(i) = stack.pop() {
+                if domains[i] == 0 {
+                    return None;
+                }
+                for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                    let neighbour = cells[i] + *offset;
+                    if !inside(neighbour) {
+                        continue;
+                    }
+                    let n = index(neighbour);
+                    let narrowed = domains[n] & self.neighbours(domains[i], direction);
+                    if narrowed != domains[n] {
+                        domains[n] = narrowed;
+                        stack.push(n);
+                    }
+                }
+            }
+
+            // lowest entropy first, with a little noise so ties don't always go the same way
+            let next = domains
+                .iter()
+                .enumerate()
+                .filter(|(_, domain)| domain.count_ones() > 1)
+                .map(|(i, domain)| (domain.count_ones() as f32 + rng.gen::<f32>() * 0.5, i))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let Some((_, i)) = next else {
+                break;
+            };
+
+            let options = (0..self.tiles.len())
+                .filter(|t| domains[i] & 1 << t != 0)
+                .collect::<Vec<_>>();
+            let total = options.iter().map(|t| self.weights[*t]).sum::<f32>();
+            let mut roll = rng.gen::<f32>() * total;
+            let choice = options
+                .iter()
+                .copied()
+                .find(|t| {
+                    roll -= self.weights[*t];
+                    roll <= 0.
+                })
+                .unwrap_or(options[options.len() - 1]);
+            domains[i] = 1 << choice;
+            stack.push(i);
+        }
+
+        Some(
+            cells
+                .into_iter()
+                .zip(domains)
+                .map(|(pos, domain)| (pos, self.tiles[domain.trailing_zeros() as usize]))
+                .collect(),
+        )
+    }
+
+    /// Fills the tiles starting at `min` one at a time with the most common tile allowed next to
+    /// the decided ones around it, without looking ahead. For pieces [`Self::solve`] gives up on:
+    /// this can still paint itself into a corner, but only the cells where nothing fits break
+    /// the rules, and those get `fallback`.
+    fn fill(
+        &self,
+        min: IVec2,
+        size: IVec2,
+        fixed: &HashMap<IVec2, TileType>,
+        fallback: TileType,
+    ) -> HashMap<IVec2, TileType> {
+        let mut tiles = HashMap::new();
+        for pos in cells(min, size) {
+            let mut domain = self.all();
+            for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                let next = pos + *offset;
+                let Some(tile) = fixed.get(&next).or_else(|| tiles.get(&next)) else {
+                    continue;
+                };
+                domain &= self
+                    .index(*tile)
+                    .map_or(0, |tile| self.allowed[opposite(direction)][tile]);
+            }
+            let tile = (0..self.tiles.len())
+                .filter(|t| domain & 1 << t != 0)
+                .max_by(|a, b| self.weights[*a].total_cmp(&self.weights[*b]))
+                .map_or(fallback, |t| self.tiles[t]);
+            tiles.insert(pos, tile);
+        }
+        tiles
+    }
+}
+
+/// Seeds for the different kinds of pieces, so they don't share random numbers.
+const CORNER: u32 = 0x1b87_3593;
+const VERTICAL_SEAM: u32 = 0xcc9e_2d51;
+const HORIZONTAL_SEAM: u32 = 0xe654_6b64;
+const INTERIOR: u32 = 0x85eb_ca6b;
+
+/// Wave Function Collapse over tile types. Chunks are built from pieces that each only depend
+/// on the seed and their position: first the 2x2 blocks where four chunks meet, then the two
+/// tiles wide strips along chunk edges between them, then the inside of each chunk. Neighbouring
+/// chunks build their shared pieces the same way, so edges always line up.
+pub struct WfcGenerator {
+    seed: u32,
+    rules: WfcRules,
+    config: WfcConfig,
+    noise: NoiseGenerator,
+}
+
+impl WfcGenerator {
+    pub fn new(seed: u32, config: &TerrainConfig) -> Self {
+        Self::with_rules(seed, config, WfcRules::from_source(&config.wfc.source))
+    }
+
+    /// Uses `rules` instead of the ones in the config, e.g. ones learned with
+    /// [`WfcRules::from_image`].
+    pub fn with_rules(seed: u32, config: &TerrainConfig, rules: WfcRules) -> Self {
+        Self {
+            seed,
+            rules,
+            config: config.wfc.clone(),
+            noise: NoiseGenerator::new(seed, config),
+        }
+    }
+
+    /// Solves a piece, restarting on contradictions. A piece that never works out is filled in
+    /// greedily instead, see [`WfcRules::fill`].
+    fn piece(
+        &self,
+        kind: u32,
+        id: IVec2,
+        min: IVec2,
+        size: IVec2,
+        fixed: &HashMap<IVec2, TileType>,
+    ) -> HashMap<IVec2, TileType> {
+        (0..=self.config.max_retries)
+            .find_map(|attempt| {
+                let seed = hash(self.seed ^ kind, id.x, id.y) as u64 | (attempt as u64) << 32;
+                self.rules.solve(min, size, fixed, seed)
+            })
+            .unwrap_or_else(|| self.rules.fill(min, size, fixed, self.config.fallback))
+    }
+
+    /// The 2x2 block around the point where chunks meet at `corner` (in chunk coordinates).
+    fn corner(&self, corner: IVec2) -> HashMap<IVec2, TileType> {
+        let point = corner * IVec2::new(CHUNK_X, CHUNK_Y);
+        self.piece(CORNER, corner, point - 1, IVec2::splat(2), &HashMap::new())
+    }
+
+    /// The strip between the corners at `corner` and the next one along `axis`.
+    fn seam(&self, corner: IVec2, axis: IVec2) -> HashMap<IVec2, TileType> {
+        let point = corner * IVec2::new(CHUNK_X, CHUNK_Y);
+        let mut fixed = self.corner(corner);
+        fixed.extend(self.corner(corner + axis));
+        let (kind, min, size) = if axis == IVec2::Y {
+            (
+                VERTICAL_SEAM,
+                point + IVec2::new(-1, 1),
+                IVec2::new(2, CHUNK_Y - 2),
+            )
+        } else {
+            (
+                HORIZONTAL_SEAM,
+                point + IVec2::new(1, -1),
+                IVec2::new(CHUNK_X - 2, 2),
+            )
+        };
+        self.piece(kind, corner, min, size, &fixed)
+    }
+}
+
+impl TerrainGenerator for WfcGenerator {
+    fn generate_chunk(&self, coord: ChunkCoord) -> ChunkData {
+        let corner = coord.as_ivec2();
+        let mut ring = HashMap::new();
+        for offset in [IVec2::ZERO, IVec2::X, IVec2::Y, IVec2::ONE] {
+            ring.extend(self.corner(corner + offset));
+        }
+        for (start, axis) in [
+            (IVec2::ZERO, IVec2::Y),
+            (IVec2::X, IVec2::Y),
+            (IVec2::ZERO, IVec2::X),
+            (IVec2::Y, IVec2::X),
+        ] {
+            ring.extend(self.seam(corner + start, axis));
+        }
+
+        let origin = coord.origin().as_ivec2();
+        let mut tiles = self.piece(
+            INTERIOR,
+            corner,
+            origin + 1,
+            IVec2::new(CHUNK_X - 2, CHUNK_Y - 2),
+            &ring,
+        );
+        tiles.extend(ring);
+
+        ChunkData::from_fn(coord, |pos| {
+            let tile = tiles
+                .get(&pos.as_ivec2())
+                .copied()
+                .unwrap_or(self.config.fallback);
+            (tile, 0.5, self.noise.biome(pos))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension},
+    };
+
+    use super::*;
+    use crate::TilePos;
+
+    fn image(width: u32, height: u32, pixels: &[[u8; 3]]) -> Image {
+        Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            pixels
+                .iter()
+                .flat_map(|[r, g, b]| [*r, *g, *b, 255])
+                .collect(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    #[test]
+    fn images_without_neighbours_are_rejected() {
+        let (green, yellow) = ([0, 255, 0], [255, 255, 0]);
+        let palette = [(green, TileType::Grass), (yellow, TileType::Sand)]
+            .into_iter()
+            .collect();
+        assert!(WfcRules::from_image(&image(0, 0, &[]), &palette).is_err());
+        assert!(WfcRules::from_image(&image(1, 1, &[green]), &palette).is_err());
+        assert!(WfcRules::from_image(&image(2, 1, &[[0; 3]; 2]), &palette).is_err());
+        let mut short = image(2, 1, &[green, yellow]);
+        short.data.truncate(4);
+        assert!(WfcRules::from_image(&short, &palette).is_err());
+
+        let rules = WfcRules::from_image(&image(1, 2, &[green, yellow]), &palette).unwrap();
+        let (grass, sand) = (rules.index(TileType::Grass), rules.index(TileType::Sand));
+        // the top row comes first, y goes up
+        assert_eq!(rules.allowed[1][sand.unwrap()], 1 << grass.unwrap());
+    }
+
+    #[test]
+    fn filling_keeps_to_the_rules_where_it_can() {
+        let rules = WfcRules::from_adjacent(
+            &[
+                (TileType::Grass, TileType::Sand),
+                (TileType::Sand, TileType::Rock),
+            ],
+            &[(TileType::Grass, 5.)].into_iter().collect(),
+        );
+        let fixed = [
+            (IVec2::new(-1, 0), TileType::Grass),
+            (IVec2::new(1, 0), TileType::Rock),
+            (IVec2::new(-1, 1), TileType::Grass),
+            (IVec2::new(1, 1), TileType::Forest),
+        ]
+        .into_iter()
+        .collect();
+        let tiles = rules.fill(IVec2::ZERO, IVec2::new(1, 2), &fixed, TileType::Snow);
+        // only sand goes between grass and rock
+        assert_eq!(tiles[&IVec2::ZERO], TileType::Sand);
+        // nothing is allowed next to forest
+        assert_eq!(tiles[&IVec2::Y], TileType::Snow);
+    }
+
+    #[test]
+    fn neighbours_are_allowed_across_chunk_edges() {
+        let config = TerrainConfig::default();
+        let generator = WfcGenerator::new(7, &config);
+        let rules = &generator.rules;
+        let mut tiles = HashMap::new();
+        for y in -2..2 {
+            for x in -2..2 {
+                let data = generator.generate_chunk(ChunkCoord::new(x, y));
+                for (local, tile) in data.tiles() {
+                    tiles.insert(data.coord.tile(local), tile);
+                }
+            }
+        }
+
+        let mut seams = 0;
+        for (pos, tile) in &tiles {
+            for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                let next = TilePos::from(pos.as_ivec2() + *offset);
+                let Some(other) = tiles.get(&next) else {
+                    continue;
+                };
+                let (a, b) = (rules.index(*tile).unwrap(), rules.index(*other).unwrap());
+                assert!(
+                    rules.allowed[direction][a] & 1 << b != 0,
+                    "{other:?} isn't allowed {offset} of {tile:?} at {pos:?}"
+                );
+                if pos.chunk() != next.chunk() {
+                    seams += 1;
+                }
+            }
+        }
+        assert!(seams > 0);
+    }
+
+    #[test]
+    fn chunks_come_out_the_same_in_any_order() {
+        let config = TerrainConfig::default();
+        let (a, b) = (WfcGenerator::new(3, &config), WfcGenerator::new(3, &config));
+        let coords = [(0, 0), (-1, 0), (0, -1), (5, -3)].map(|(x, y)| ChunkCoord::new(x, y));
+        let first = coords.map(|coord| a.generate_chunk(coord).tiles().collect::<Vec<_>>());
+        let second = coords
+            .into_iter()
+            .rev()
+            .map(|coord| b.generate_chunk(coord).tiles().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert!(first.iter().eq(second.iter().rev()));
+    }
+}
@@ -1,17 +1,95 @@
+use std::path::Path;
+
 use bevy::prelude::*;
-use proc_gen::Terrain;
+use proc_gen::{
+    CaveGenerator, ChunkLoader, ErosionConfig, MapExport, Mask, NoiseGenerator, NoiseNode,
+    RiverConfig, Terrain, TerrainConfig, TerrainGenerator, TilePos, WfcGenerator, WorldSeed,
+};
+
+fn setup(mut commands: Commands) {
+    commands.spawn((Camera2d, ChunkLoader));
+}
+
+fn flag(name: &str) -> bool {
+    std::env::args().any(|arg| arg == name)
+}
+
+/// The argument following `name`, if it's there and parses.
+fn value<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let mut args = std::env::args().skip_while(|arg| arg != name).skip(1);
+    args.next()?.parse().ok()
+}
+
+/// `--island` surrounds the origin with sea, `--rivers` and `--erosion` turn those on.
+fn config() -> TerrainConfig {
+    let mut config = TerrainConfig::default();
+    if flag("--island") {
+        config.mask = Some(Mask::Continent {
+            center: IVec2::ZERO,
+            radius: 100.,
+            falloff: 0.4,
+            shape: NoiseNode::perlin(7).fbm(3, 2., 0.5).scale(1. / 40.),
+            strength: 0.4,
+        });
+    }
+    if flag("--rivers") {
+        config.rivers = Some(RiverConfig::default());
+    }
+    if flag("--erosion") {
+        config.erosion = Some(ErosionConfig::default());
+    }
+    config
+}
+
+fn generator(seed: u32, config: &TerrainConfig) -> Box<dyn TerrainGenerator> {
+    if flag("--caves") {
+        Box::new(CaveGenerator::new(seed, config))
+    } else if flag("--wfc") {
+        Box::new(WfcGenerator::new(seed, config))
+    } else {
+        Box::new(NoiseGenerator::new(seed, config))
+    }
+}
+
+/// `--export` writes `heights.png` and `tiles.png` of the `--size` tiles around the origin
+/// instead of opening a window.
+fn export(seed: u32) {
+    let half = value("--size").unwrap_or(256) / 2;
+    let generator = generator(seed, &config());
+    let export = MapExport::render(
+        &generator,
+        TilePos::new(-half, -half),
+        TilePos::new(half - 1, half - 1),
+    );
+    match export.save(Path::new("heights.png"), Path::new("tiles.png")) {
+        Ok(()) => println!("exported seed {seed}"),
+        Err(err) => eprintln!("export failed: {err}"),
+    }
+}
+
 fn main() {
-    App::new()
-        .add_plugins((DefaultPlugins
-            .set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: "Procedural Terrain".into(),
-                    position: WindowPosition::Automatic,
-                    ..Default::default()
-                }),
+    let seed = value("--seed");
+    if flag("--export") {
+        export(seed.unwrap_or(0));
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins((DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "Procedural Terrain".into(),
+                position: WindowPosition::Automatic,
                 ..Default::default()
-            })
-            .set(ImagePlugin::default_nearest()),))
-        .add_plugins(Terrain)
-        .run();
+            }),
+            ..Default::default()
+        })
+        .set(ImagePlugin::default_nearest()),))
+        .insert_resource(config())
+        .add_plugins(Terrain::new(generator))
+        .add_systems(Startup, setup);
+    if let Some(seed) = seed {
+        app.insert_resource(WorldSeed(seed));
+    }
+    app.run();
 }
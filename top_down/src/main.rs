@@ -1,10 +1,15 @@
+use std::f32::consts::SQRT_2;
+
 use bevy::prelude::*;
 use player::{
     input::{self},
     Player,
 };
 
-use proc_gen::Terrain;
+use proc_gen::{
+    ChunkLoader, ChunkSettings, Connected, LoadWorld, NoiseGenerator, SaveWorld, Terrain,
+    TerrainConfigHandle, CHUNK_X, CHUNK_Y,
+};
 struct TopDown;
 
 mod physics;
@@ -13,11 +18,13 @@ mod player;
 #[derive(Component)]
 struct MainCam;
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(TerrainConfigHandle(assets.load("world.terrain.ron")));
     let camera = Camera2d;
     commands.spawn((
         camera,
         MainCam,
+        ChunkLoader,
         Camera {
             clear_color: ClearColorConfig::Custom(Color::linear_rgb(
                 24. / 255.,
@@ -45,18 +52,61 @@ fn move_camera(
     }
 }
 
+/// Noise terrain without pockets the player could get stuck in. Every decoration prop is a sprite
+/// named after it, flipped at random so repeats stand out less.
+fn terrain() -> Terrain {
+    let terrain =
+        Terrain::new(|seed, config| Connected::new(NoiseGenerator::new(seed, config), config));
+    ["tree", "pine", "rock", "flower", "cactus"]
+        .into_iter()
+        .fold(terrain, |terrain, prop| {
+            terrain.with_prop(prop, move |decoration, assets| Sprite {
+                flip_x: decoration.variant < 0.5,
+                ..Sprite::from_image(assets.load(format!("props/{prop}.png")))
+            })
+        })
+}
+
+const SAVE_FILE: &str = "world.save.ron";
+
+fn save_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut save: EventWriter<SaveWorld>,
+    mut load: EventWriter<LoadWorld>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        save.send(SaveWorld(SAVE_FILE.into()));
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        load.send(LoadWorld(SAVE_FILE.into()));
+    }
+}
+
 impl Plugin for TopDown {
     fn build(&self, app: &mut App) {
+        // the camera starts on the player and loads a circle of chunks, which has to take in
+        // the corners of the spawn search
+        let chunk = CHUNK_X.min(CHUNK_Y);
+        let reach = (Player::spawn_reach() + chunk - 1) / chunk;
+        let load_radius = (reach as f32 * SQRT_2).ceil() as i32;
+        app.insert_resource(ChunkSettings {
+            load_radius,
+            unload_radius: load_radius + 2,
+            ..default()
+        });
         app.add_systems(Startup, (setup, player::setup, input::setup));
         app.add_systems(PreUpdate, (input::kb_movement, input::mouse_world));
         app.add_systems(
             Update,
             (
+                (player::respawn, player::place_spawn).chain(),
                 player::movement,
                 player::aim,
                 player::shoot,
                 physics::apply_physics,
+                player::projectile_hits.after(physics::apply_physics),
                 move_camera,
+                save_keys,
             ),
         );
     }
@@ -75,6 +125,6 @@ fn main() {
                 }),
         )
         .add_plugins(TopDown)
-        .add_plugins(Terrain)
+        .add_plugins(terrain())
         .run();
 }
@@ -5,13 +5,16 @@ use bevy::{
     image::Image,
     math::{Quat, Vec2, Vec3},
     prelude::{
-        BuildChildren, Bundle, Children, Commands, Component, Query, Res, ResMut, Resource,
-        Transform, With, Without,
+        BuildChildren, Bundle, Children, Commands, Component, DespawnRecursiveExt, Entity,
+        EventReader, Query, Res, ResMut, Resource, Transform, With, Without,
     },
     sprite::Sprite,
     time::{Time, Timer, TimerMode},
 };
 use input::{Cursor, PlayerActions};
+use proc_gen::{
+    ChunkCoord, Regenerated, TerrainColliders, TerrainEditor, TerrainMap, TilePos, BLOCK_SIZE,
+};
 
 use crate::physics::PhysicsBody;
 
@@ -23,13 +26,33 @@ pub struct Player;
 #[derive(Component)]
 pub struct Arrow;
 
+/// On a player that still has to be moved to a safe spot once the terrain around it is loaded.
+#[derive(Component)]
+pub struct Spawning;
+
 impl Player {
     pub const Z: f32 = 1.;
+    /// Collision box, a bit smaller than the sprite so the player fits between rocks.
+    pub const HALF_SIZE: Vec2 = Vec2::splat(12.);
+    /// How many tiles from where it was spawned the player may be moved to.
+    pub const SPAWN_SEARCH: i32 = 16;
+
+    /// Clearance the player needs around it to fit.
+    pub fn clearance() -> f32 {
+        Self::HALF_SIZE.length()
+    }
+
+    /// How many tiles around the spawn `place_spawn` looks at, the search plus the clearance.
+    /// These have to be in the chunks loaded around the spawn, see `TopDown`.
+    pub fn spawn_reach() -> i32 {
+        Self::SPAWN_SEARCH + (Self::clearance() / BLOCK_SIZE).ceil() as i32
+    }
 }
 
 pub fn character(image: Handle<Image>) -> impl Bundle {
     (
         Player,
+        Spawning,
         Sprite {
             custom_size: Some(Vec2 { x: 50., y: 50. }),
             ..Sprite::from_image(image)
@@ -63,14 +86,62 @@ pub fn setup(assets: Res<AssetServer>, mut commands: Commands) {
     )));
 }
 
+/// The terrain under the player changes completely when the config (re)loads or a save is
+/// loaded, so it has to find a safe spot all over again.
+pub fn respawn(
+    mut commands: Commands,
+    mut events: EventReader<Regenerated>,
+    player: Query<Entity, With<Player>>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    for entity in &player {
+        commands.entity(entity).insert(Spawning);
+    }
+}
+
+/// Waits for the whole search area to load so a spot further away isn't picked just because the
+/// closer ones aren't there yet. If there's no safe spot the player stays where it is.
+pub fn place_spawn(
+    mut commands: Commands,
+    map: Res<TerrainMap>,
+    mut player: Query<(Entity, &mut Transform), With<Spawning>>,
+) {
+    let Ok((entity, mut transform)) = player.get_single_mut() else {
+        return;
+    };
+    let near = transform.translation.truncate();
+    // every tile find_spawn might look at
+    let start = TilePos::from_world(near);
+    let reach = Player::spawn_reach();
+    let (min, max) = (
+        start.offset(-reach, -reach).chunk(),
+        start.offset(reach, reach).chunk(),
+    );
+    if !(min.y..=max.y)
+        .flat_map(|y| (min.x..=max.x).map(move |x| ChunkCoord::new(x, y)))
+        .all(|coord| map.chunk(coord).is_some())
+    {
+        return;
+    }
+    if let Some(spawn) = map.find_spawn(near, Player::clearance(), Player::SPAWN_SEARCH) {
+        transform.translation = spawn.extend(transform.translation.z);
+    }
+    commands.entity(entity).remove::<Spawning>();
+}
+
 pub fn movement(
     actions: ResMut<PlayerActions>,
-    mut player: Query<(&Player, &mut Transform)>,
+    mut player: Query<(&Player, &mut Transform), Without<Spawning>>,
     time: Res<Time>,
+    colliders: Res<TerrainColliders>,
 ) {
     player.iter_mut().for_each(|(_, mut transform)| {
-        transform.translation +=
-            time.delta_secs() * actions.axis.normalize_or_zero().extend(0.) * 100.;
+        let motion = time.delta_secs() * actions.axis.normalize_or_zero() * 100.;
+        let center =
+            colliders.move_and_slide(transform.translation.truncate(), Player::HALF_SIZE, motion);
+        transform.translation = center.extend(transform.translation.z);
     });
 }
 
@@ -106,7 +177,11 @@ pub struct Game {
 }
 
 #[derive(Component)]
-struct Projectile;
+pub struct Projectile;
+
+impl Projectile {
+    pub const RADIUS: f32 = 2.;
+}
 
 #[derive(Resource)]
 pub struct ShootTimer(Timer);
@@ -140,3 +215,30 @@ pub fn shoot(
         ));
     }
 }
+
+/// Arrows stop at solid terrain and dig out the tile they hit.
+pub fn projectile_hits(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Transform), With<Projectile>>,
+    colliders: Res<TerrainColliders>,
+    mut editor: TerrainEditor,
+) {
+    for (entity, transform) in &projectiles {
+        let pos = transform.translation.truncate();
+        if !colliders.overlaps_circle(pos, Projectile::RADIUS) {
+            continue;
+        }
+        let hit = editor
+            .map()
+            .region(
+                TilePos::from_world(pos - Projectile::RADIUS),
+                TilePos::from_world(pos + Projectile::RADIUS),
+            )
+            .map(|(tile, _)| tile)
+            .find(|tile| editor.map().is_solid(*tile));
+        if let Some(tile) = hit {
+            editor.dig(tile);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}